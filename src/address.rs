@@ -0,0 +1,221 @@
+use crate::Electrum2DescriptorError;
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::bip32::{ChildNumber, Xpub};
+#[cfg(feature = "wallet_file")]
+use bitcoin::blockdata::{opcodes::all::OP_CHECKMULTISIG, script::Builder};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::hex::DisplayHex;
+use bitcoin::secp256k1::Secp256k1;
+#[cfg(feature = "wallet_file")]
+use bitcoin::PublicKey;
+use bitcoin::{Address, CompressedPublicKey, Network, NetworkKind, Script, ScriptBuf};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// Electrum keys and wallets from which addresses can be derived
+pub trait AddressSource {
+    /// Returns the scriptPubKey at `chain/index`, where chain 0 is receiving and 1 is change
+    fn script_pubkey(&self, chain: u32, index: u32) -> Result<ScriptBuf, Electrum2DescriptorError>;
+
+    /// Returns whether the addresses are for mainnet or a test network
+    fn network_kind(&self) -> Result<NetworkKind, Electrum2DescriptorError>;
+}
+
+/// Where an address was found by [`find_addresses`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AddressMatch {
+    /// The address as it was searched for
+    pub address: String,
+    /// Position of the key or wallet in the searched sources
+    pub source: usize,
+    pub chain: u32,
+    pub index: u32,
+}
+
+/// Walks the receiving and change chain of every source up to `depth` addresses and reports where the given addresses were found.
+/// An address is only searched in the sources of its network and fails if none of the sources is of its network.
+pub fn find_addresses(
+    addresses: &[&str],
+    sources: &[&dyn AddressSource],
+    depth: u32,
+) -> Result<Vec<AddressMatch>, Electrum2DescriptorError> {
+    let networks = sources
+        .iter()
+        .map(|source| source.network_kind())
+        .collect::<Result<Vec<_>, _>>()?;
+    let parsed = addresses
+        .iter()
+        .map(|addr| Ok((Address::from_str(addr)?, *addr)))
+        .collect::<Result<Vec<_>, Electrum2DescriptorError>>()?;
+    if let Some(first) = networks.first() {
+        for (address, _addr) in &parsed {
+            if !networks
+                .iter()
+                .any(|network| require_network(address.clone(), *network).is_ok())
+            {
+                require_network(address.clone(), *first)?;
+            }
+        }
+    }
+    let mut matches = Vec::new();
+    for (source_idx, (source, network)) in sources.iter().zip(networks).enumerate() {
+        let wanted = parsed
+            .iter()
+            .filter_map(|(address, addr)| {
+                let address = require_network(address.clone(), network).ok()?;
+                Some((address.script_pubkey(), *addr))
+            })
+            .collect::<HashMap<_, _>>();
+        for chain in [0, 1] {
+            for index in 0..depth {
+                if let Some(addr) = wanted.get(&source.script_pubkey(chain, index)?) {
+                    matches.push(AddressMatch {
+                        address: addr.to_string(),
+                        source: source_idx,
+                        chain,
+                        index,
+                    });
+                }
+            }
+        }
+    }
+    Ok(matches)
+}
+
+/// A derived address with the scripthash electrum servers index it by
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptHash {
+    pub chain: u32,
+    pub index: u32,
+    pub address: Address,
+    pub script_pubkey: ScriptBuf,
+    /// The reversed SHA256 of the scriptPubKey in hex, as expected by the `blockchain.scripthash.*` methods
+    pub scripthash: String,
+}
+
+/// Returns the electrum protocol scripthash of a scriptPubKey: its SHA256, byte reversed, in hex
+pub fn electrum_scripthash(script_pubkey: &Script) -> String {
+    let mut hash = sha256::Hash::hash(script_pubkey.as_bytes()).to_byte_array();
+    hash.reverse();
+    hash.to_lower_hex_string()
+}
+
+/// Derives address, scriptPubKey and scripthash of the given indexes of `chain`
+pub(crate) fn scripthashes(
+    source: &dyn AddressSource,
+    network: Network,
+    chain: u32,
+    indexes: Range<u32>,
+) -> Result<Vec<ScriptHash>, Electrum2DescriptorError> {
+    indexes
+        .map(|index| {
+            let script_pubkey = source.script_pubkey(chain, index)?;
+            Ok(ScriptHash {
+                chain,
+                index,
+                address: Address::from_script(&script_pubkey, network)?,
+                scripthash: electrum_scripthash(&script_pubkey),
+                script_pubkey,
+            })
+        })
+        .collect()
+}
+
+/// Returns the network to encode addresses for. Electrum keys only distinguish mainnet and testnet.
+pub(crate) fn network(kind: NetworkKind) -> Network {
+    match kind {
+        NetworkKind::Main => Network::Bitcoin,
+        NetworkKind::Test => Network::Testnet,
+    }
+}
+
+/// Checks that an address is encoded for the network of the keys, where a test key may be used on any test network
+pub(crate) fn require_network(
+    address: Address<NetworkUnchecked>,
+    kind: NetworkKind,
+) -> Result<Address, Electrum2DescriptorError> {
+    match kind {
+        NetworkKind::Test if address.is_valid_for_network(Network::Regtest) => {
+            Ok(address.assume_checked())
+        }
+        _ => Ok(address.require_network(network(kind))?),
+    }
+}
+
+/// Derives the public key at `chain/index` below the given xpub
+fn derive(
+    xpub: &Xpub,
+    chain: u32,
+    index: u32,
+) -> Result<CompressedPublicKey, Electrum2DescriptorError> {
+    let secp = Secp256k1::verification_only();
+    let path = [
+        ChildNumber::from_normal_idx(chain)?,
+        ChildNumber::from_normal_idx(index)?,
+    ];
+    Ok(xpub.derive_pub(&secp, &path)?.to_pub())
+}
+
+/// Derives the scriptPubKey at `chain/index` of a single signature script kind
+pub(crate) fn singlesig_script(
+    kind: &str,
+    xpub: &Xpub,
+    chain: u32,
+    index: u32,
+) -> Result<ScriptBuf, Electrum2DescriptorError> {
+    let pk = derive(xpub, chain, index)?;
+    let script = match kind {
+        "pkh" => ScriptBuf::new_p2pkh(&pk.pubkey_hash()),
+        "sh(wpkh" => ScriptBuf::new_p2sh(&ScriptBuf::new_p2wpkh(&pk.wpubkey_hash()).script_hash()),
+        "wpkh" => ScriptBuf::new_p2wpkh(&pk.wpubkey_hash()),
+        "tr" => ScriptBuf::new_p2tr(&Secp256k1::verification_only(), pk.0.into(), None),
+        "sh(wsh" | "wsh" => {
+            return Err(Electrum2DescriptorError::MultisigKindKey(kind.to_string()))
+        }
+        _ => {
+            return Err(Electrum2DescriptorError::UnknownScriptKind(
+                kind.to_string(),
+            ))
+        }
+    };
+    Ok(script)
+}
+
+/// Derives the scriptPubKey at `chain/index` of a `sortedmulti` script.
+/// The kind is the one of the cosigner keys, where "pkh" stands for legacy p2sh multisig.
+#[cfg(feature = "wallet_file")]
+pub(crate) fn multisig_script(
+    kind: &str,
+    threshold: u8,
+    xpubs: &[Xpub],
+    chain: u32,
+    index: u32,
+) -> Result<ScriptBuf, Electrum2DescriptorError> {
+    let mut pks = xpubs
+        .iter()
+        .map(|xpub| derive(xpub, chain, index))
+        .collect::<Result<Vec<_>, _>>()?;
+    pks.sort_by_key(|pk| pk.to_bytes());
+    let redeem_script = pks
+        .iter()
+        .fold(Builder::new().push_int(threshold.into()), |builder, pk| {
+            builder.push_key(&PublicKey::from(*pk))
+        })
+        .push_int(pks.len() as i64)
+        .push_opcode(OP_CHECKMULTISIG)
+        .into_script();
+    let script = match kind {
+        "pkh" => ScriptBuf::new_p2sh(&redeem_script.script_hash()),
+        "sh(wsh" => {
+            ScriptBuf::new_p2sh(&ScriptBuf::new_p2wsh(&redeem_script.wscript_hash()).script_hash())
+        }
+        "wsh" => ScriptBuf::new_p2wsh(&redeem_script.wscript_hash()),
+        _ => {
+            return Err(Electrum2DescriptorError::UnknownScriptKind(
+                kind.to_string(),
+            ))
+        }
+    };
+    Ok(script)
+}
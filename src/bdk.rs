@@ -0,0 +1,25 @@
+//! Conversion between electrum wallets and `bdk_wallet` wallets
+use crate::{Electrum2DescriptorError, ElectrumWalletFile};
+use bdk_wallet::{bitcoin::Network, KeychainKind, Wallet};
+
+impl ElectrumWalletFile {
+    /// Create an in-memory bdk wallet with the receiving chain as external and the change chain as internal keychain.
+    /// The descriptors contain the key origins and the xprvs where available, so the bdk wallet can sign.
+    pub fn to_bdk_wallet(&self, network: Network) -> Result<Wallet, Electrum2DescriptorError> {
+        let descriptors = self.to_descriptors_with_origins()?;
+        Ok(Wallet::create(descriptors.external, descriptors.change)
+            .network(network)
+            .create_wallet_no_persist()?)
+    }
+
+    /// Construct from the public descriptors of a bdk wallet.
+    /// The internal keychain must be the change chain of the external one, as electrum has no other layout.
+    pub fn from_bdk_wallet(wallet: &Wallet) -> Result<Self, Electrum2DescriptorError> {
+        let [external, internal] =
+            [KeychainKind::External, KeychainKind::Internal].map(|keychain| {
+                let desc = wallet.public_descriptor(keychain).to_string();
+                desc.split('#').next().unwrap_or_default().to_string()
+            });
+        ElectrumWalletFile::from_descriptors(&external, &internal)
+    }
+}
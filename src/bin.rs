@@ -1,35 +1,449 @@
 #[cfg(feature = "wallet_file")]
-use libelectrum2descriptors::ElectrumWalletFile;
 use libelectrum2descriptors::{
-    Electrum2DescriptorError, ElectrumExtendedKey, ElectrumExtendedPrivKey, ElectrumExtendedPubKey,
+    bitcoin_core::{ListDescriptors, Timestamp},
+    ElectrumExtendedKey, ElectrumWalletFile,
+};
+use libelectrum2descriptors::{
+    find_addresses, AddressSource, Descriptors, Electrum2DescriptorError, ElectrumExtendedPrivKey,
+    ElectrumExtendedPubKey, KeyPolicy, ScriptHash,
 };
-#[cfg(feature = "wallet_file")]
-use std::path::Path;
 use std::str::FromStr;
+#[cfg(feature = "wallet_file")]
+use std::{io::Write, path::Path};
 
 fn main() -> Result<(), Electrum2DescriptorError> {
     let mut args = std::env::args();
     args.next(); // first is program name
     let err_msg =
         "You must specify an extended public or private key or an electrum wallet file as first argument";
-    let electrum_x = args
+    let mut electrum_x = args
         .next()
-        .ok_or_else(|| Electrum2DescriptorError::GenericBorrow(err_msg))?;
-    let descriptor = ElectrumExtendedPrivKey::from_str(&electrum_x)
-        .map(|e| e.to_descriptors())
-        .or_else(|_| ElectrumExtendedPubKey::from_str(&electrum_x).map(|e| e.to_descriptors()));
+        .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?;
+    match electrum_x.as_str() {
+        "addresses" => return print_addresses(args),
+        "scripthashes" => return print_scripthashes(args),
+        "find" => return print_found_addresses(args),
+        "taproot" => return print_taproot_descriptors(args),
+        "sortedmulti" => return print_sortedmulti_descriptors(args),
+        #[cfg(feature = "wallet_file")]
+        "core" => return print_core_requests(args),
+        #[cfg(feature = "wallet_file")]
+        "from-core" => return write_core_wallets(args),
+        #[cfg(feature = "wallet_file")]
+        "sparrow" => return print_sparrow_json(args),
+        #[cfg(feature = "electrum_server")]
+        "scan" => return print_used_indexes(args),
+        _ => {}
+    }
+    let mut policy = KeyPolicy::PrivateWhereAvailable;
+    if electrum_x == "--keys" {
+        policy = parse_key_policy(args.next())?;
+        electrum_x = args
+            .next()
+            .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?;
+    }
+    let descriptor = if let Ok(exprv) = ElectrumExtendedPrivKey::from_str(&electrum_x) {
+        exprv.to_descriptors_with_policy(policy)
+    } else if let Ok(expub) = ElectrumExtendedPubKey::from_str(&electrum_x) {
+        expub.to_descriptors_with_policy(policy)
+    } else {
+        wallet_file_descriptors(&electrum_x, policy, err_msg)
+    };
+
+    println!("{:?}", descriptor?);
+    Ok(())
+}
+
+/// Reads the descriptors from an electrum wallet file
+#[cfg(feature = "wallet_file")]
+fn wallet_file_descriptors(
+    wallet_file: &str,
+    policy: KeyPolicy,
+    err_msg: &'static str,
+) -> Result<Descriptors, Electrum2DescriptorError> {
+    let wallet_file = Path::new(wallet_file);
+    if !wallet_file.is_file() {
+        return Err(Electrum2DescriptorError::GenericBorrow(err_msg));
+    }
+    ElectrumWalletFile::from_file(wallet_file)?.to_descriptors_with_policy(policy, false)
+}
+
+#[cfg(not(feature = "wallet_file"))]
+fn wallet_file_descriptors(
+    _wallet_file: &str,
+    _policy: KeyPolicy,
+    err_msg: &'static str,
+) -> Result<Descriptors, Electrum2DescriptorError> {
+    Err(Electrum2DescriptorError::GenericBorrow(err_msg))
+}
+
+/// Reads the taproot descriptors of the key in a single signature electrum wallet file
+#[cfg(feature = "wallet_file")]
+fn wallet_file_taproot_descriptors(
+    wallet_file: &str,
+    policy: KeyPolicy,
+    err_msg: &'static str,
+) -> Result<Descriptors, Electrum2DescriptorError> {
+    let wallet_file = Path::new(wallet_file);
+    if !wallet_file.is_file() {
+        return Err(Electrum2DescriptorError::GenericBorrow(err_msg));
+    }
+    ElectrumWalletFile::from_file(wallet_file)?.to_taproot_descriptors(policy)
+}
+
+#[cfg(not(feature = "wallet_file"))]
+fn wallet_file_taproot_descriptors(
+    _wallet_file: &str,
+    _policy: KeyPolicy,
+    err_msg: &'static str,
+) -> Result<Descriptors, Electrum2DescriptorError> {
+    Err(Electrum2DescriptorError::GenericBorrow(err_msg))
+}
+
+/// Parses the value of `--keys`: public, private or require-private
+fn parse_key_policy(arg: Option<String>) -> Result<KeyPolicy, Electrum2DescriptorError> {
+    arg.ok_or(Electrum2DescriptorError::GenericBorrow(
+        "--keys expects public, private or require-private",
+    ))?
+    .parse()
+}
+
+/// `addresses <electrum xpub> [count]` prints the first addresses of the receiving and change chain
+fn print_addresses(mut args: std::env::Args) -> Result<(), Electrum2DescriptorError> {
+    let err_msg = "Usage: addresses <electrum xpub> [count]";
+    let expub = args
+        .next()
+        .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?;
+    let expub = ElectrumExtendedPubKey::from_str(&expub)?;
+    let count = match args.next() {
+        Some(count) => count
+            .parse()
+            .map_err(|_| Electrum2DescriptorError::GenericBorrow(err_msg))?,
+        None => 20,
+    };
+
+    for chain in [0, 1] {
+        for (index, address) in expub.addresses(chain, 0..count).enumerate() {
+            println!("{}/{} {}", chain, index, address?);
+        }
+    }
+    Ok(())
+}
+
+/// `scripthashes <electrum xpub or wallet file> [count]` prints address, scriptPubKey and electrum scripthash of the first indexes of both chains
+fn print_scripthashes(mut args: std::env::Args) -> Result<(), Electrum2DescriptorError> {
+    let err_msg = "Usage: scripthashes <electrum xpub or wallet file> [count]";
+    let source = args
+        .next()
+        .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?;
+    let count = match args.next() {
+        Some(count) => count
+            .parse()
+            .map_err(|_| Electrum2DescriptorError::GenericBorrow(err_msg))?,
+        None => 20,
+    };
+
+    for chain in [0, 1] {
+        for derived in scripthashes(&source, chain, count)? {
+            println!(
+                "{}/{} {} {} {}",
+                derived.chain,
+                derived.index,
+                derived.address,
+                derived.script_pubkey.to_hex_string(),
+                derived.scripthash
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Derives the scripthashes of an electrum xpub or, if built with wallet file support, of a descriptor or wallet file
+fn scripthashes(
+    source: &str,
+    chain: u32,
+    count: u32,
+) -> Result<Vec<ScriptHash>, Electrum2DescriptorError> {
+    let scripthashes = ElectrumExtendedPubKey::from_str(source)
+        .and_then(|expub| expub.scripthashes(chain, 0..count));
     #[cfg(feature = "wallet_file")]
-    let descriptor = descriptor.or_else(|_| {
-        let wallet_file = Path::new(&electrum_x)
-            .canonicalize()
-            .map_err(|_| Electrum2DescriptorError::GenericBorrow(err_msg))?;
-        if !wallet_file.exists() {
-            return Err(Electrum2DescriptorError::GenericBorrow(err_msg));
-        }
-        let wallet = ElectrumWalletFile::from_file(wallet_file.as_path())?;
-        wallet.to_descriptors()
+    let scripthashes = scripthashes.or_else(|err| {
+        // keep the error of the key unless the argument can only be meant as descriptor or wallet file
+        if source.contains('(') || Path::new(source).is_file() {
+            load_wallet(source)?.scripthashes(chain, 0..count)
+        } else {
+            Err(err)
+        }
     });
+    scripthashes
+}
+
+/// `taproot [--keys POLICY] <electrum key or wallet file>` prints `tr()` descriptors using the same extended key
+fn print_taproot_descriptors(mut args: std::env::Args) -> Result<(), Electrum2DescriptorError> {
+    let err_msg = "Usage: taproot [--keys public|private|require-private] <electrum extended key or single signature wallet file>";
+    let mut policy = KeyPolicy::PublicOnly;
+    let mut electrum_x = args
+        .next()
+        .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?;
+    if electrum_x == "--keys" {
+        policy = parse_key_policy(args.next())?;
+        electrum_x = args
+            .next()
+            .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?;
+    }
+    let descriptor = if let Ok(exprv) = ElectrumExtendedPrivKey::from_str(&electrum_x) {
+        exprv.to_taproot()?.to_descriptors_with_policy(policy)
+    } else if let Ok(expub) = ElectrumExtendedPubKey::from_str(&electrum_x) {
+        expub.to_taproot()?.to_descriptors_with_policy(policy)
+    } else {
+        wallet_file_taproot_descriptors(&electrum_x, policy, err_msg)
+    };
 
     println!("{:?}", descriptor?);
     Ok(())
 }
+
+/// `sortedmulti <threshold> <electrum xpub>...` prints the multisig descriptors of cosigner keys like Zpubs
+fn print_sortedmulti_descriptors(mut args: std::env::Args) -> Result<(), Electrum2DescriptorError> {
+    let err_msg = "Usage: sortedmulti <threshold> <electrum xpub>...";
+    let threshold = args
+        .next()
+        .and_then(|t| t.parse().ok())
+        .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?;
+    let keys = args
+        .map(|key| ElectrumExtendedPubKey::from_str(&key))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    println!(
+        "{:?}",
+        ElectrumExtendedPubKey::sortedmulti_descriptors(threshold, &keys)?
+    );
+    Ok(())
+}
+
+/// `find [--depth N] <address>... <electrum key or wallet file>...` searches the addresses in the keys and wallets
+fn print_found_addresses(mut args: std::env::Args) -> Result<(), Electrum2DescriptorError> {
+    let err_msg = "Usage: find [--depth N] <address>... <electrum extended key or wallet file>...";
+    let mut depth = 1000;
+    let mut addresses = Vec::new();
+    let mut sources: Vec<(String, Box<dyn AddressSource>)> = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--depth" {
+            depth = args
+                .next()
+                .and_then(|d| d.parse().ok())
+                .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?;
+        } else if let Ok(source) = load_address_source(&arg) {
+            sources.push((arg, source));
+        } else {
+            addresses.push(arg);
+        }
+    }
+    if addresses.is_empty() || sources.is_empty() {
+        return Err(Electrum2DescriptorError::GenericBorrow(err_msg));
+    }
+
+    let addresses = addresses.iter().map(|a| a.as_str()).collect::<Vec<_>>();
+    let refs = sources.iter().map(|(_, s)| s.as_ref()).collect::<Vec<_>>();
+    for found in find_addresses(&addresses, &refs, depth)? {
+        println!(
+            "{} {} {}/{}",
+            found.address, sources[found.source].0, found.chain, found.index
+        );
+    }
+    Ok(())
+}
+
+/// `core [--birthday UNIXTIME] [--range N] [--label NAME] [--keys POLICY] <electrum key or wallet file>`
+/// prints the `createwallet` and `importdescriptors` requests for bitcoind, one per line.
+/// Without `--birthday` the rescan starts at the wallet's first known transaction, else at the genesis block.
+#[cfg(feature = "wallet_file")]
+fn print_core_requests(mut args: std::env::Args) -> Result<(), Electrum2DescriptorError> {
+    let err_msg = "Usage: core [--birthday UNIXTIME] [--range N] [--label NAME] [--keys public|private|require-private] <electrum extended key or wallet file> (the rescan starts at --birthday, else the wallet's first known transaction, else the genesis block)";
+    let mut timestamp = None;
+    let mut range = 1000;
+    let mut label = "electrum".to_string();
+    let mut policy = KeyPolicy::PublicOnly;
+    let mut wallet = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--birthday" => {
+                let time = args
+                    .next()
+                    .and_then(|t| t.parse().ok())
+                    .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?;
+                timestamp = Some(Timestamp::Time(time));
+            }
+            "--range" => {
+                range = args
+                    .next()
+                    .and_then(|r| r.parse().ok())
+                    .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?
+            }
+            "--label" => {
+                label = args
+                    .next()
+                    .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?
+            }
+            "--keys" => policy = parse_key_policy(args.next())?,
+            _ => wallet = Some(load_wallet(&arg)?),
+        }
+    }
+    let wallet = wallet.ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?;
+
+    println!(
+        "{}",
+        wallet.to_core_createwallet(&label, policy)?.to_json()?
+    );
+    println!(
+        "{}",
+        wallet
+            .to_core_importdescriptors(timestamp, range, policy)?
+            .to_json()?
+    );
+    Ok(())
+}
+
+/// `from-core <listdescriptors.json> [directory]` writes an electrum wallet file per pair of active descriptors of a Core wallet.
+/// Existing files are never replaced and only the public descriptors are printed.
+#[cfg(feature = "wallet_file")]
+fn write_core_wallets(mut args: std::env::Args) -> Result<(), Electrum2DescriptorError> {
+    let err_msg = "Usage: from-core <listdescriptors json file> [output directory]";
+    let json = args
+        .next()
+        .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?;
+    let directory = args.next().unwrap_or_else(|| ".".to_string());
+    let listed = ListDescriptors::from_json(&std::fs::read_to_string(json)?)?;
+    let name = &listed.wallet_name;
+    if name.contains(['/', '\\']) || name.contains("..") {
+        return Err(Electrum2DescriptorError::InvalidWalletName(name.clone()));
+    }
+    let import = listed.to_electrum_wallets();
+    for (i, wallet) in import.wallets.iter().enumerate() {
+        let wallet_file = Path::new(&directory).join(format!("{}_{}", name, i + 1));
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&wallet_file)?;
+        write!(file, "{}", wallet)?;
+        println!(
+            "{} {}",
+            wallet_file.display(),
+            wallet
+                .to_descriptors_with_policy(KeyPolicy::PublicOnly, false)?
+                .external
+        );
+    }
+    for skipped in import.skipped {
+        eprintln!("skipped {}: {}", skipped.desc, skipped.reason);
+    }
+    Ok(())
+}
+
+/// `sparrow <descriptor or wallet file> [name]` prints a watch-only Sparrow wallet in its JSON format.
+/// Sparrow needs the key origins, which a bare electrum key doesn't have.
+#[cfg(feature = "wallet_file")]
+fn print_sparrow_json(mut args: std::env::Args) -> Result<(), Electrum2DescriptorError> {
+    let err_msg = "Usage: sparrow <descriptor with key origins or wallet file> [name]";
+    let wallet = args
+        .next()
+        .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?;
+    let name = args.next().unwrap_or_else(|| "electrum".to_string());
+    println!("{}", load_wallet(&wallet)?.to_sparrow_json(&name)?);
+    Ok(())
+}
+
+/// `scan [--gap N] [--timeout SECONDS] <host:port[:s]> <electrum key, descriptor or wallet file>` asks an electrum server
+/// for the last used index of both chains, over TLS if the server ends in `:s` like in electrum's server setting
+#[cfg(feature = "electrum_server")]
+fn print_used_indexes(mut args: std::env::Args) -> Result<(), Electrum2DescriptorError> {
+    use libelectrum2descriptors::electrum_server::{
+        ElectrumServer, DEFAULT_GAP_LIMIT, DEFAULT_TIMEOUT,
+    };
+
+    let err_msg = "Usage: scan [--gap N] [--timeout SECONDS] <host:port[:s]> <electrum extended key, descriptor or wallet file>";
+    let mut gap_limit = DEFAULT_GAP_LIMIT;
+    let mut timeout = DEFAULT_TIMEOUT;
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--gap" => {
+                gap_limit = args
+                    .next()
+                    .and_then(|g| g.parse().ok())
+                    .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?
+            }
+            "--timeout" => {
+                timeout = args
+                    .next()
+                    .and_then(|t| t.parse().ok())
+                    .map(std::time::Duration::from_secs)
+                    .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let (server, wallet) = match positional.as_slice() {
+        [server, wallet] => (server, wallet),
+        _ => return Err(Electrum2DescriptorError::GenericBorrow(err_msg)),
+    };
+
+    let wallet = load_wallet(wallet)?;
+    let used = match server.strip_suffix(":s") {
+        #[cfg(feature = "electrum_server_tls")]
+        Some(address) => {
+            let (host, port) = address
+                .rsplit_once(':')
+                .and_then(|(host, port)| Some((host, port.parse().ok()?)))
+                .ok_or(Electrum2DescriptorError::GenericBorrow(err_msg))?;
+            ElectrumServer::connect_tls(host, port, timeout)?.scan(&wallet, gap_limit)?
+        }
+        #[cfg(not(feature = "electrum_server_tls"))]
+        Some(_) => {
+            return Err(Electrum2DescriptorError::GenericBorrow(
+                "TLS connections need the electrum_server_tls feature",
+            ))
+        }
+        None => {
+            let address = server.strip_suffix(":t").unwrap_or(server);
+            ElectrumServer::connect(address, timeout)?.scan(&wallet, gap_limit)?
+        }
+    };
+    let format = |index: Option<u32>| index.map_or("unused".to_string(), |i| i.to_string());
+    println!("receiving {}", format(used.receiving));
+    println!("change {}", format(used.change));
+    println!("range {}", used.import_range(gap_limit));
+    Ok(())
+}
+
+/// Interprets the argument as electrum extended key, as descriptor or as path to an electrum wallet file and returns it as wallet
+#[cfg(feature = "wallet_file")]
+fn load_wallet(arg: &str) -> Result<ElectrumWalletFile, Electrum2DescriptorError> {
+    if arg.contains('(') {
+        return ElectrumWalletFile::from_descriptor(arg);
+    }
+    // a key that parses but can't be converted reports why instead of being taken for a file name
+    let descriptors = if let Ok(exprv) = ElectrumExtendedPrivKey::from_str(arg) {
+        exprv.to_descriptors()?
+    } else if let Ok(expub) = ElectrumExtendedPubKey::from_str(arg) {
+        expub.to_descriptors()?
+    } else {
+        return ElectrumWalletFile::from_file(Path::new(arg));
+    };
+    ElectrumWalletFile::from_descriptor(&descriptors.external)
+}
+
+/// Interprets the argument as electrum extended key or as path to an electrum wallet file
+fn load_address_source(arg: &str) -> Result<Box<dyn AddressSource>, Electrum2DescriptorError> {
+    if let Ok(exprv) = ElectrumExtendedPrivKey::from_str(arg) {
+        return Ok(Box::new(exprv));
+    }
+    if let Ok(expub) = ElectrumExtendedPubKey::from_str(arg) {
+        return Ok(Box::new(expub));
+    }
+    #[cfg(feature = "wallet_file")]
+    if Path::new(arg).is_file() {
+        return Ok(Box::new(ElectrumWalletFile::from_file(Path::new(arg))?));
+    }
+    Err(Electrum2DescriptorError::UnknownType)
+}
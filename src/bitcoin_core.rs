@@ -0,0 +1,267 @@
+//! Requests for the Bitcoin Core RPCs `createwallet` and `importdescriptors` to migrate an electrum wallet into a Core descriptor wallet,
+//! and the import of Core's `listdescriptors` output into electrum wallets
+use crate::checksum::with_checksum;
+use crate::electrum_wallet_file::{DEFAULT_CHANGE_ADDRESSES, DEFAULT_RECEIVING_ADDRESSES};
+use crate::{Descriptors, Electrum2DescriptorError, ElectrumWalletFile, KeyPolicy};
+use serde::{Deserialize, Serialize, Serializer};
+
+/// The id of the JSON-RPC requests
+const RPC_ID: &str = "electrum2descriptors";
+
+/// The JSON-RPC 1.0 request body as expected by bitcoind
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct RpcRequest<T> {
+    pub jsonrpc: &'static str,
+    pub id: &'static str,
+    pub method: &'static str,
+    pub params: T,
+}
+
+impl<T: Serialize> RpcRequest<T> {
+    fn new(method: &'static str, params: T) -> Self {
+        RpcRequest {
+            jsonrpc: "1.0",
+            id: RPC_ID,
+            method,
+            params,
+        }
+    }
+
+    /// Serialize the request as JSON, ready to be posted to bitcoind
+    pub fn to_json(&self) -> Result<String, Electrum2DescriptorError> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+/// The named parameters of `createwallet` for an empty descriptor wallet to import the descriptors into
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct CreateWallet {
+    pub wallet_name: String,
+    pub disable_private_keys: bool,
+    pub blank: bool,
+    pub descriptors: bool,
+}
+
+/// The time from which on bitcoind rescans the blockchain for transactions of an imported descriptor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timestamp {
+    /// Only new transactions, no rescan
+    Now,
+    /// Unix time of the wallet birthday
+    Time(u64),
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Timestamp::Now => serializer.serialize_str("now"),
+            Timestamp::Time(time) => serializer.serialize_u64(*time),
+        }
+    }
+}
+
+/// One descriptor of an `importdescriptors` request.
+/// Core refuses labels on ranged descriptors, hence a wallet label goes into the name of [`CreateWallet`].
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct ImportDescriptor {
+    /// The descriptor including its checksum
+    pub desc: String,
+    pub active: bool,
+    pub internal: bool,
+    /// First and last index to derive, inclusive
+    pub range: [u32; 2],
+    pub timestamp: Timestamp,
+}
+
+/// Build the `importdescriptors` entries for the external and the change descriptor, both active.
+/// `range` is the number of addresses to derive per chain.
+pub fn import_descriptors(
+    descriptors: &Descriptors,
+    timestamp: Timestamp,
+    range: u32,
+) -> Result<Vec<ImportDescriptor>, Electrum2DescriptorError> {
+    [(&descriptors.external, false), (&descriptors.change, true)]
+        .iter()
+        .map(|(desc, internal)| {
+            Ok(ImportDescriptor {
+                desc: with_checksum(desc)?,
+                active: true,
+                internal: *internal,
+                range: [0, range.saturating_sub(1)],
+                timestamp,
+            })
+        })
+        .collect()
+}
+
+impl ElectrumWalletFile {
+    /// Build the `createwallet` request for a blank descriptor wallet.
+    /// Private keys are only enabled if the same `policy` imports xprvs, i.e. allows them and a keystore holds one.
+    pub fn to_core_createwallet(
+        &self,
+        wallet_name: &str,
+        policy: KeyPolicy,
+    ) -> Result<RpcRequest<CreateWallet>, Electrum2DescriptorError> {
+        // fails early for `RequirePrivate` if a keystore has no xprv
+        self.to_descriptors_with_policy(policy, false)?;
+        let has_private_keys = match policy {
+            KeyPolicy::PublicOnly => false,
+            KeyPolicy::PrivateWhereAvailable | KeyPolicy::RequirePrivate => {
+                self.keystores().iter().any(|ks| ks.xprv.is_some())
+            }
+        };
+        Ok(RpcRequest::new(
+            "createwallet",
+            CreateWallet {
+                wallet_name: wallet_name.to_string(),
+                disable_private_keys: !has_private_keys,
+                blank: true,
+                descriptors: true,
+            },
+        ))
+    }
+
+    /// Build the `importdescriptors` request with the key origins of the keystores and the keys selected by `policy`.
+    /// Without `timestamp` the rescan starts at the [`ElectrumWalletFile::import_timestamp`] derived from the wallet history.
+    pub fn to_core_importdescriptors(
+        &self,
+        timestamp: Option<Timestamp>,
+        range: u32,
+        policy: KeyPolicy,
+    ) -> Result<RpcRequest<(Vec<ImportDescriptor>,)>, Electrum2DescriptorError> {
+        let descriptors = self.to_descriptors_with_policy(policy, true)?;
+        let timestamp = timestamp.unwrap_or_else(|| self.import_timestamp());
+        Ok(RpcRequest::new(
+            "importdescriptors",
+            (import_descriptors(&descriptors, timestamp, range)?,),
+        ))
+    }
+
+    /// The rescan start for an import into Core: the time of the wallet's birthday, else the genesis block.
+    /// Without history the wallet may just never have been synced, so only a full rescan is sure to find its funds.
+    pub fn import_timestamp(&self) -> Timestamp {
+        Timestamp::Time(
+            self.birthday()
+                .and_then(|birthday| birthday.timestamp)
+                .unwrap_or(0),
+        )
+    }
+}
+
+/// The result of the `listdescriptors` RPC, with or without private keys
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct ListDescriptors {
+    pub wallet_name: String,
+    pub descriptors: Vec<ListedDescriptor>,
+}
+
+/// One descriptor of the `listdescriptors` result
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct ListedDescriptor {
+    /// The descriptor including its checksum
+    pub desc: String,
+    pub timestamp: u64,
+    pub active: bool,
+    /// Only present for active descriptors
+    #[serde(default)]
+    pub internal: bool,
+    /// Only present for ranged descriptors
+    #[serde(default)]
+    pub range: Option<[u32; 2]>,
+    /// The next index to hand out, called `next_index` by newer versions of Core
+    #[serde(default)]
+    pub next: Option<u32>,
+    #[serde(default)]
+    pub next_index: Option<u32>,
+}
+
+impl ListedDescriptor {
+    /// The descriptor without its checksum
+    fn descriptor(&self) -> &str {
+        self.desc.split('#').next().unwrap_or_default()
+    }
+}
+
+/// A descriptor of `listdescriptors` which has no electrum representation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SkippedDescriptor {
+    pub desc: String,
+    pub reason: String,
+}
+
+/// The electrum wallets built from the descriptors of a Core wallet
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoreImport {
+    /// One wallet per pair of active external and internal descriptors
+    pub wallets: Vec<ElectrumWalletFile>,
+    /// The descriptors which couldn't be converted
+    pub skipped: Vec<SkippedDescriptor>,
+}
+
+impl ListDescriptors {
+    /// Parse the JSON result of `listdescriptors`
+    pub fn from_json(json: &str) -> Result<Self, Electrum2DescriptorError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Pair the active external and internal descriptors of the same keys and script type into electrum wallets.
+    /// Inactive descriptors, unpaired ones and those electrum can't represent, like `tr` or `combo`, are reported as skipped.
+    pub fn to_electrum_wallets(&self) -> CoreImport {
+        let mut wallets = Vec::new();
+        let mut skipped = Vec::new();
+        let mut skip = |listed: &ListedDescriptor, reason: String| {
+            skipped.push(SkippedDescriptor {
+                desc: listed.desc.clone(),
+                reason,
+            })
+        };
+        let (active, inactive): (Vec<_>, Vec<_>) =
+            self.descriptors.iter().partition(|listed| listed.active);
+        let (mut internals, externals): (Vec<_>, Vec<_>) =
+            active.into_iter().partition(|listed| listed.internal);
+
+        for external in externals {
+            if let Err(e) = ElectrumWalletFile::from_descriptor(external.descriptor()) {
+                skip(external, e.to_string());
+                continue;
+            }
+            let paired = internals.iter().enumerate().find_map(|(i, internal)| {
+                ElectrumWalletFile::from_descriptors(external.descriptor(), internal.descriptor())
+                    .ok()
+                    .map(|wallet| (i, wallet))
+            });
+            match paired {
+                Some((i, mut wallet)) => {
+                    let internal = internals.remove(i);
+                    // electrum should know at least the addresses Core handed out already
+                    let [receiving, change] = [
+                        (external, DEFAULT_RECEIVING_ADDRESSES),
+                        (internal, DEFAULT_CHANGE_ADDRESSES),
+                    ]
+                    .map(|(listed, default)| {
+                        listed.next_index.or(listed.next).unwrap_or(0).max(default)
+                    });
+                    match wallet.generate_addresses(receiving, change) {
+                        Ok(()) => wallets.push(wallet),
+                        Err(e) => skip(external, e.to_string()),
+                    }
+                }
+                None => skip(external, "no matching internal descriptor".to_string()),
+            }
+        }
+        for internal in internals {
+            let reason = match ElectrumWalletFile::from_descriptor(
+                &internal.descriptor().replace("/1/*", "/0/*"),
+            ) {
+                Err(e) => e.to_string(),
+                Ok(_) => "no matching external descriptor".to_string(),
+            };
+            skip(internal, reason);
+        }
+        for listed in inactive {
+            skip(listed, "inactive descriptor".to_string());
+        }
+
+        CoreImport { wallets, skipped }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::Electrum2DescriptorError;
+
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn poly_mod(mut c: u64, val: u64) -> u64 {
+    let c0 = c >> 35;
+    c = ((c & 0x7ffffffff) << 5) ^ val;
+    for (i, generator) in [
+        0xf5dee51989,
+        0xa9fdca3312,
+        0x1bab10e32d,
+        0x3706b1677a,
+        0x644d626ffd,
+    ]
+    .iter()
+    .enumerate()
+    {
+        if c0 & (1 << i) != 0 {
+            c ^= generator;
+        }
+    }
+    c
+}
+
+/// Computes the checksum of a descriptor as defined by bitcoin core
+/// https://github.com/bitcoin/bitcoin/blob/master/src/script/descriptor.cpp
+pub fn descriptor_checksum(desc: &str) -> Result<String, Electrum2DescriptorError> {
+    let mut c = 1;
+    let mut cls = 0;
+    let mut clscount = 0;
+    for ch in desc.chars() {
+        let pos = INPUT_CHARSET
+            .find(ch)
+            .ok_or(Electrum2DescriptorError::InvalidDescriptorCharacter(ch))?
+            as u64;
+        c = poly_mod(c, pos & 31);
+        cls = cls * 3 + (pos >> 5);
+        clscount += 1;
+        if clscount == 3 {
+            c = poly_mod(c, cls);
+            cls = 0;
+            clscount = 0;
+        }
+    }
+    if clscount > 0 {
+        c = poly_mod(c, cls);
+    }
+    for _ in 0..8 {
+        c = poly_mod(c, 0);
+    }
+    c ^= 1;
+
+    Ok((0..8)
+        .map(|j| CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char)
+        .collect())
+}
+
+/// Appends the checksum to a descriptor: `desc#checksum`
+pub fn with_checksum(desc: &str) -> Result<String, Electrum2DescriptorError> {
+    Ok(format!("{}#{}", desc, descriptor_checksum(desc)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptor_checksum() {
+        // from https://github.com/bitcoin/bitcoin/blob/master/doc/descriptors.md
+        assert_eq!(with_checksum("wpkh([d34db33f/84h/0h/0h]xpub6DJ2dNUysrn5Vt36jH2KLBT2i1auw1tTSSomg8PhqNiUtx8QX2SvC9nrHu81fT41fvDUnhMjEzQgXnQjKEu3oaqMSzhSrHMxyyoEAmUHQbY/0/*)").unwrap(), "wpkh([d34db33f/84h/0h/0h]xpub6DJ2dNUysrn5Vt36jH2KLBT2i1auw1tTSSomg8PhqNiUtx8QX2SvC9nrHu81fT41fvDUnhMjEzQgXnQjKEu3oaqMSzhSrHMxyyoEAmUHQbY/0/*)#cjjspncu");
+        assert_eq!(descriptor_checksum("wsh(sortedmulti(2,[27d81095/48h/1h/0h/2h]tpubDEcw4ooTbmw62zBKdkYepoP3z4WWugdeRzPHHAbk8XVsPfBE9AAZMNghiqwtdFgtabaeppBTPmezUkRkQZidLcSJp3XTASbMakHcYauWehZ/0/*,[6bfac2d6/48h/100h/0h/2h]tpubDEbkvhmJoZMq3SUNqEf3aEsubvqsCUPc7rroHkGERgS7qA1gQVMxUPrgzth6x43odirLohwf4aMHpvcnWi3jCB2xkizv8T4B2KqLRZVLC6K/0/*))").unwrap(), "lzam3k2d");
+        assert!(matches!(
+            descriptor_checksum("wpkh(é)"),
+            Err(Electrum2DescriptorError::InvalidDescriptorCharacter('é'))
+        ));
+    }
+}
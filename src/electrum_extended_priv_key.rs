@@ -1,8 +1,11 @@
-use crate::{Descriptors, Electrum2DescriptorError, ElectrumExtendedKey};
+use crate::{
+    address, AddressSource, Descriptors, Electrum2DescriptorError, ElectrumExtendedKey,
+    ElectrumExtendedPubKey, KeyPolicy,
+};
 use bitcoin::base58;
-use bitcoin::bip32::{ChainCode, ChildNumber, Fingerprint, Xpriv};
+use bitcoin::bip32::{ChainCode, ChildNumber, Fingerprint, Xpriv, Xpub};
 use bitcoin::secp256k1;
-use bitcoin::{Network, NetworkKind};
+use bitcoin::{Network, NetworkKind, ScriptBuf};
 use std::convert::TryInto;
 use std::str::FromStr;
 
@@ -125,12 +128,20 @@ impl ElectrumExtendedKey for ElectrumExtendedPrivKey {
     }
 
     /// Returns internal and external descriptor
-    fn to_descriptors(&self) -> Descriptors {
-        let xprv = self.xprv.to_string();
-        let closing_parenthesis = if self.kind.contains('(') { ")" } else { "" };
-        let [external, change] =
-            [0, 1].map(|i| format!("{}({}/{}/*){}", self.kind, xprv, i, closing_parenthesis));
-        Descriptors { external, change }
+    fn to_descriptors(&self) -> Result<Descriptors, Electrum2DescriptorError> {
+        Descriptors::singlesig(&self.kind, &self.xprv.to_string())
+    }
+}
+
+impl AddressSource for ElectrumExtendedPrivKey {
+    fn script_pubkey(&self, chain: u32, index: u32) -> Result<ScriptBuf, Electrum2DescriptorError> {
+        let secp = secp256k1::Secp256k1::new();
+        let xpub = Xpub::from_priv(&secp, &self.xprv);
+        address::singlesig_script(&self.kind, &xpub, chain, index)
+    }
+
+    fn network_kind(&self) -> Result<NetworkKind, Electrum2DescriptorError> {
+        Ok(self.xprv.network)
     }
 }
 
@@ -140,6 +151,31 @@ impl ElectrumExtendedPrivKey {
         ElectrumExtendedPrivKey { xprv, kind }
     }
 
+    /// Returns the same key with the taproot script kind, for moving an electrum wallet to `tr(xprv/0/*)` descriptors.
+    /// Electrum itself doesn't support taproot, so the result can't be converted back to electrum format.
+    /// Multisig cosigner keys are rejected, as their wallet isn't a single key wallet.
+    pub fn to_taproot(&self) -> Result<Self, Electrum2DescriptorError> {
+        if self.kind == "sh(wsh" || self.kind == "wsh" {
+            return Err(Electrum2DescriptorError::MultisigKindKey(self.kind.clone()));
+        }
+        Ok(ElectrumExtendedPrivKey::new(self.xprv, "tr".to_string()))
+    }
+
+    /// Returns the descriptors with the xprv, or with the xpub for [`KeyPolicy::PublicOnly`]
+    pub fn to_descriptors_with_policy(
+        &self,
+        policy: KeyPolicy,
+    ) -> Result<Descriptors, Electrum2DescriptorError> {
+        match policy {
+            KeyPolicy::PublicOnly => {
+                let secp = secp256k1::Secp256k1::signing_only();
+                let xpub = Xpub::from_priv(&secp, &self.xprv);
+                ElectrumExtendedPubKey::new(xpub, self.kind.clone()).to_descriptors()
+            }
+            KeyPolicy::PrivateWhereAvailable | KeyPolicy::RequirePrivate => self.to_descriptors(),
+        }
+    }
+
     /// Returns the xprv
     pub fn xprv(&self) -> &Xpriv {
         &self.xprv
@@ -148,10 +184,15 @@ impl ElectrumExtendedPrivKey {
     /// converts to electrum format
     pub fn electrum_xprv(&self) -> Result<String, Electrum2DescriptorError> {
         let sentinels = initialize_sentinels();
+        if !sentinels.iter().any(|sent| sent.2 == self.kind) {
+            return Err(Electrum2DescriptorError::ElectrumUnsupportedScriptKind(
+                self.kind.clone(),
+            ));
+        }
         let sentinel = sentinels
             .iter()
             .find(|sent| NetworkKind::from(sent.1) == self.xprv.network && sent.2 == self.kind)
-            .ok_or_else(|| Electrum2DescriptorError::UnknownType)?;
+            .ok_or(Electrum2DescriptorError::UnknownType)?;
         let mut data = Vec::from(&sentinel.0[..]);
         data.push(self.xprv.depth);
         data.extend(self.xprv.parent_fingerprint.as_bytes());
@@ -190,7 +231,7 @@ mod tests {
         let electrum_xprv = ElectrumExtendedPrivKey::from_str("yprvAHwhK6RbpuS3dgCYHM5jc2ZvEKd7Bi61u9FVhYMpgMSuZS613T1xxQeKTffhrHY79hZ5PsskBjcc6C2V7DrnsMsNaGDaWev3GLRQRgV7hxF").unwrap();
         assert_eq!(electrum_xprv.xprv.to_string(),"xprv9y7S1RkggDtZnP1RSzJ7PwUR4MUfF66Wz2jGv9TwJM52WLGmnnrQLLzBSTi7rNtBk4SGeQHBj5G4CuQvPXSn58BmhvX9vk6YzcMm37VuNYD");
         assert_eq!(electrum_xprv.kind, "sh(wpkh");
-        let descriptors = electrum_xprv.to_descriptors();
+        let descriptors = electrum_xprv.to_descriptors().unwrap();
         assert_eq!(descriptors.external, "sh(wpkh(xprv9y7S1RkggDtZnP1RSzJ7PwUR4MUfF66Wz2jGv9TwJM52WLGmnnrQLLzBSTi7rNtBk4SGeQHBj5G4CuQvPXSn58BmhvX9vk6YzcMm37VuNYD/0/*))");
         assert_eq!(descriptors.change, "sh(wpkh(xprv9y7S1RkggDtZnP1RSzJ7PwUR4MUfF66Wz2jGv9TwJM52WLGmnnrQLLzBSTi7rNtBk4SGeQHBj5G4CuQvPXSn58BmhvX9vk6YzcMm37VuNYD/1/*))");
         let xprv = electrum_xprv.xprv();
@@ -206,6 +247,40 @@ mod tests {
         assert_eq!(electrum_xprv.electrum_xprv().unwrap(), "yprvAHwhK6RbpuS3dgCYHM5jc2ZvEKd7Bi61u9FVhYMpgMSuZS613T1xxQeKTffhrHY79hZ5PsskBjcc6C2V7DrnsMsNaGDaWev3GLRQRgV7hxF");
     }
 
+    #[test]
+    fn test_taproot() {
+        let electrum_xprv = ElectrumExtendedPrivKey::from_str("yprvAHwhK6RbpuS3dgCYHM5jc2ZvEKd7Bi61u9FVhYMpgMSuZS613T1xxQeKTffhrHY79hZ5PsskBjcc6C2V7DrnsMsNaGDaWev3GLRQRgV7hxF").unwrap();
+        let taproot = electrum_xprv.to_taproot().unwrap();
+        let descriptors = taproot.to_descriptors().unwrap();
+        assert_eq!(descriptors.external, "tr(xprv9y7S1RkggDtZnP1RSzJ7PwUR4MUfF66Wz2jGv9TwJM52WLGmnnrQLLzBSTi7rNtBk4SGeQHBj5G4CuQvPXSn58BmhvX9vk6YzcMm37VuNYD/0/*)");
+        assert_eq!(descriptors.change, "tr(xprv9y7S1RkggDtZnP1RSzJ7PwUR4MUfF66Wz2jGv9TwJM52WLGmnnrQLLzBSTi7rNtBk4SGeQHBj5G4CuQvPXSn58BmhvX9vk6YzcMm37VuNYD/1/*)");
+        assert!(matches!(
+            taproot.electrum_xprv(),
+            Err(Electrum2DescriptorError::ElectrumUnsupportedScriptKind(_))
+        ));
+
+        let cosigner = ElectrumExtendedPrivKey::new(*electrum_xprv.xprv(), "sh(wsh".to_string());
+        assert!(matches!(
+            cosigner.to_taproot(),
+            Err(Electrum2DescriptorError::MultisigKindKey(kind)) if kind == "sh(wsh"
+        ));
+    }
+
+    #[test]
+    fn test_key_policy() {
+        let electrum_xprv = ElectrumExtendedPrivKey::from_str("yprvAHwhK6RbpuS3dgCYHM5jc2ZvEKd7Bi61u9FVhYMpgMSuZS613T1xxQeKTffhrHY79hZ5PsskBjcc6C2V7DrnsMsNaGDaWev3GLRQRgV7hxF").unwrap();
+        let public = electrum_xprv
+            .to_descriptors_with_policy(KeyPolicy::PublicOnly)
+            .unwrap();
+        assert!(public.external.starts_with("sh(wpkh(xpub"));
+        for policy in [KeyPolicy::PrivateWhereAvailable, KeyPolicy::RequirePrivate] {
+            assert_eq!(
+                electrum_xprv.to_descriptors_with_policy(policy).unwrap(),
+                electrum_xprv.to_descriptors().unwrap()
+            );
+        }
+    }
+
     #[test]
     fn test_vprv_roundtrip() {
         let elxprv = "yprvAHwhK6RbpuS3dgCYHM5jc2ZvEKd7Bi61u9FVhYMpgMSuZS613T1xxQeKTffhrHY79hZ5PsskBjcc6C2V7DrnsMsNaGDaWev3GLRQRgV7hxF";
@@ -1,9 +1,14 @@
-use crate::{Descriptors, Electrum2DescriptorError, ElectrumExtendedKey};
+use crate::{
+    address, find_addresses, AddressMatch, AddressSource, Descriptors, Electrum2DescriptorError,
+    ElectrumExtendedKey, KeyPolicy, ScriptHash,
+};
 use bitcoin::base58;
 use bitcoin::bip32::{ChainCode, ChildNumber, Fingerprint, Xpub};
 use bitcoin::secp256k1;
-use bitcoin::{Network, NetworkKind};
+use bitcoin::{Address, Network, NetworkKind, ScriptBuf};
+use std::collections::HashSet;
 use std::convert::TryInto;
+use std::ops::Range;
 use std::str::FromStr;
 
 pub struct ElectrumExtendedPubKey {
@@ -124,12 +129,18 @@ impl ElectrumExtendedKey for ElectrumExtendedPubKey {
     }
 
     /// Returns internal and external descriptor
-    fn to_descriptors(&self) -> Descriptors {
-        let xpub = self.xpub.to_string();
-        let closing_parenthesis = if self.kind.contains('(') { ")" } else { "" };
-        let [external, change] =
-            [0, 1].map(|i| format!("{}({}/{}/*){}", self.kind, xpub, i, closing_parenthesis));
-        Descriptors { external, change }
+    fn to_descriptors(&self) -> Result<Descriptors, Electrum2DescriptorError> {
+        Descriptors::singlesig(&self.kind, &self.xpub.to_string())
+    }
+}
+
+impl AddressSource for ElectrumExtendedPubKey {
+    fn script_pubkey(&self, chain: u32, index: u32) -> Result<ScriptBuf, Electrum2DescriptorError> {
+        address::singlesig_script(&self.kind, &self.xpub, chain, index)
+    }
+
+    fn network_kind(&self) -> Result<NetworkKind, Electrum2DescriptorError> {
+        Ok(self.xpub.network)
     }
 }
 
@@ -139,18 +150,140 @@ impl ElectrumExtendedPubKey {
         ElectrumExtendedPubKey { xpub, kind }
     }
 
+    /// Infers the script kind of a plain BIP32 xpub from addresses known to belong to it.
+    /// Every single signature kind is tried, walking both chains up to `depth`, and the first one producing all the addresses is returned together with where they were found.
+    pub fn detect_kind(
+        xpub: Xpub,
+        addresses: &[&str],
+        depth: u32,
+    ) -> Result<(Self, Vec<AddressMatch>), Electrum2DescriptorError> {
+        // find_addresses reports every scriptPubKey once, however often it was given
+        let wanted = addresses
+            .iter()
+            .map(|addr| {
+                Ok(
+                    address::require_network(Address::from_str(addr)?, xpub.network)?
+                        .script_pubkey(),
+                )
+            })
+            .collect::<Result<HashSet<_>, Electrum2DescriptorError>>()?;
+        for kind in ["pkh", "sh(wpkh", "wpkh", "tr"] {
+            let expub = ElectrumExtendedPubKey::new(xpub, kind.to_string());
+            let matches = find_addresses(addresses, &[&expub], depth)?;
+            if !wanted.is_empty() && matches.len() == wanted.len() {
+                return Ok((expub, matches));
+            }
+        }
+        Err(Electrum2DescriptorError::ScriptKindNotDetected)
+    }
+
+    /// Returns the same key with the taproot script kind, for moving an electrum wallet to `tr(xpub/0/*)` descriptors.
+    /// Electrum itself doesn't support taproot, so the result can't be converted back to electrum format.
+    /// Multisig cosigner keys are rejected, as their wallet isn't a single key wallet.
+    pub fn to_taproot(&self) -> Result<Self, Electrum2DescriptorError> {
+        if self.kind == "sh(wsh" || self.kind == "wsh" {
+            return Err(Electrum2DescriptorError::MultisigKindKey(self.kind.clone()));
+        }
+        Ok(ElectrumExtendedPubKey::new(self.xpub, "tr".to_string()))
+    }
+
+    /// Returns `sortedmulti` descriptors of cosigner keys sharing one multisig kind:
+    /// Ypub/Upub for `sh(wsh(`, Zpub/Vpub for `wsh(` and xpub/tpub for legacy `sh(`.
+    pub fn sortedmulti_descriptors(
+        threshold: u8,
+        keys: &[ElectrumExtendedPubKey],
+    ) -> Result<Descriptors, Electrum2DescriptorError> {
+        if keys.len() < 2 {
+            return Err(Electrum2DescriptorError::MultisigFewSigners);
+        }
+        if threshold == 0 {
+            return Err(Electrum2DescriptorError::ZeroThreshold);
+        }
+        if threshold as usize > keys.len() {
+            return Err(Electrum2DescriptorError::NumberSignaturesKeyStores(
+                threshold,
+                keys.len(),
+            ));
+        }
+        let first = &keys[0];
+        if !["pkh", "sh(wsh", "wsh"].contains(&first.kind.as_str()) {
+            return Err(Electrum2DescriptorError::SinglesigKindKey(
+                first.kind.clone(),
+            ));
+        }
+        for key in keys {
+            if key.kind != first.kind {
+                return Err(Electrum2DescriptorError::MixedScriptKinds(
+                    first.kind.clone(),
+                    key.kind.clone(),
+                ));
+            }
+            if key.xpub.network != first.xpub.network {
+                return Err(Electrum2DescriptorError::MixedNetworks);
+            }
+        }
+        let xpubs = keys
+            .iter()
+            .map(|key| key.xpub.to_string())
+            .collect::<Vec<_>>();
+        Ok(Descriptors::sortedmulti(&first.kind, threshold, &xpubs))
+    }
+
+    /// Returns the descriptors with the xpub, failing for [`KeyPolicy::RequirePrivate`]
+    pub fn to_descriptors_with_policy(
+        &self,
+        policy: KeyPolicy,
+    ) -> Result<Descriptors, Electrum2DescriptorError> {
+        match policy {
+            KeyPolicy::RequirePrivate => Err(Electrum2DescriptorError::MissingPrivateKey(0)),
+            KeyPolicy::PublicOnly | KeyPolicy::PrivateWhereAvailable => self.to_descriptors(),
+        }
+    }
+
     /// Returns the xpub
     pub fn xpub(&self) -> &Xpub {
         &self.xpub
     }
 
+    /// Returns the address at `chain/index`, where chain 0 is receiving and 1 is change
+    pub fn address(&self, chain: u32, index: u32) -> Result<Address, Electrum2DescriptorError> {
+        let script = self.script_pubkey(chain, index)?;
+        Ok(Address::from_script(
+            &script,
+            address::network(self.xpub.network),
+        )?)
+    }
+
+    /// Returns the addresses of `chain` for the given range of indexes
+    pub fn addresses(
+        &self,
+        chain: u32,
+        indexes: Range<u32>,
+    ) -> impl Iterator<Item = Result<Address, Electrum2DescriptorError>> + '_ {
+        indexes.map(move |index| self.address(chain, index))
+    }
+
+    /// Returns address, scriptPubKey and electrum scripthash of `chain` for the given range of indexes
+    pub fn scripthashes(
+        &self,
+        chain: u32,
+        indexes: Range<u32>,
+    ) -> Result<Vec<ScriptHash>, Electrum2DescriptorError> {
+        address::scripthashes(self, address::network(self.xpub.network), chain, indexes)
+    }
+
     /// converts to electrum format
     pub fn electrum_xpub(&self) -> Result<String, Electrum2DescriptorError> {
         let sentinels = initialize_sentinels();
+        if !sentinels.iter().any(|sent| sent.2 == self.kind) {
+            return Err(Electrum2DescriptorError::ElectrumUnsupportedScriptKind(
+                self.kind.clone(),
+            ));
+        }
         let sentinel = sentinels
             .iter()
             .find(|sent| NetworkKind::from(sent.1) == self.xpub.network && sent.2 == self.kind)
-            .ok_or_else(|| Electrum2DescriptorError::UnknownType)?;
+            .ok_or(Electrum2DescriptorError::UnknownType)?;
         let mut data = Vec::from(&sentinel.0[..]);
         data.push(self.xpub.depth);
         data.extend(self.xpub.parent_fingerprint.as_bytes());
@@ -190,13 +323,77 @@ mod tests {
         let electrum_xpub = ElectrumExtendedPubKey::from_str("vpub5VXaSncXqxLbdmvrC4Y8z9CszPwuEscADoetWhfrxDFzPUbL5nbVtanYDkrVEutkv9n5A5aCcvRC9swbjDKgHjCZ2tAeae8VsBuPbS8KpXv").unwrap();
         assert_eq!(electrum_xpub.xpub.to_string(),"tpubD9ZjaMn3rbP1cAVwJy6UcEjFfTLT7W6DbfHdS3Wn48meExtVfKmiH9meWCrSmE9qXLYbGcHC5LxLcdfLZTzwme23qAJoRzRhzbd68dHeyjp");
         assert_eq!(electrum_xpub.kind, "wpkh");
-        let descriptors = electrum_xpub.to_descriptors();
+        let descriptors = electrum_xpub.to_descriptors().unwrap();
         assert_eq!(descriptors.external, "wpkh(tpubD9ZjaMn3rbP1cAVwJy6UcEjFfTLT7W6DbfHdS3Wn48meExtVfKmiH9meWCrSmE9qXLYbGcHC5LxLcdfLZTzwme23qAJoRzRhzbd68dHeyjp/0/*)");
         assert_eq!(descriptors.change, "wpkh(tpubD9ZjaMn3rbP1cAVwJy6UcEjFfTLT7W6DbfHdS3Wn48meExtVfKmiH9meWCrSmE9qXLYbGcHC5LxLcdfLZTzwme23qAJoRzRhzbd68dHeyjp/1/*)");
         let xpub = electrum_xpub.xpub();
         assert_eq!(xpub.to_string(), "tpubD9ZjaMn3rbP1cAVwJy6UcEjFfTLT7W6DbfHdS3Wn48meExtVfKmiH9meWCrSmE9qXLYbGcHC5LxLcdfLZTzwme23qAJoRzRhzbd68dHeyjp");
     }
 
+    #[test]
+    fn test_sortedmulti() {
+        let keys = ["Vpub5mUs4UNPA6T3VAmcTWTJ2nCV2oAEFQqBNQQDH62NQNpdAMSyL2Nd3vZXF6uQfNeiCst7asUapZWM9AKmsYCK1BjUrEVhiVm9M4qnbHvDRDe", "Vpub5mTgvNLEMssnVd4fezZgnDhLefVaYCb94GsjHfgrhXksbrHRbMa2AwjWX9eczB1dG19oZmEnVNCeVLWoygXQrkL8nuyAgWDxnePWUQ5fE3N"]
+            .iter()
+            .map(|key| ElectrumExtendedPubKey::from_str(key).unwrap())
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            keys[0].to_descriptors(),
+            Err(Electrum2DescriptorError::MultisigKindKey(kind)) if kind == "wsh"
+        ));
+        assert!(matches!(
+            keys[0].address(0, 0),
+            Err(Electrum2DescriptorError::MultisigKindKey(kind)) if kind == "wsh"
+        ));
+        assert!(matches!(
+            keys[0].scripthashes(0, 0..1),
+            Err(Electrum2DescriptorError::MultisigKindKey(kind)) if kind == "wsh"
+        ));
+
+        let descriptors = ElectrumExtendedPubKey::sortedmulti_descriptors(2, &keys).unwrap();
+        assert_eq!(descriptors.external, "wsh(sortedmulti(2,tpubDEcw4ooTbmw62zBKdkYepoP3z4WWugdeRzPHHAbk8XVsPfBE9AAZMNghiqwtdFgtabaeppBTPmezUkRkQZidLcSJp3XTASbMakHcYauWehZ/0/*,tpubDEbkvhmJoZMq3SUNqEf3aEsubvqsCUPc7rroHkGERgS7qA1gQVMxUPrgzth6x43odirLohwf4aMHpvcnWi3jCB2xkizv8T4B2KqLRZVLC6K/0/*))");
+        assert_eq!(
+            descriptors.change,
+            descriptors.external.replace("/0/*", "/1/*")
+        );
+        let descriptor: miniscript::Descriptor<DescriptorPublicKey> =
+            descriptors.external.parse().unwrap();
+        let first = descriptor
+            .at_derivation_index(0)
+            .unwrap()
+            .address(Network::Testnet)
+            .unwrap();
+        assert_eq!(
+            first.to_string(),
+            "tb1q4n590s3wlqtsz4g6jzmfc8whn0fvun3vmjzpuj5740gr56jfjy2qpglrkv"
+        );
+
+        assert!(matches!(
+            ElectrumExtendedPubKey::sortedmulti_descriptors(3, &keys),
+            Err(Electrum2DescriptorError::NumberSignaturesKeyStores(3, 2))
+        ));
+        assert!(matches!(
+            ElectrumExtendedPubKey::sortedmulti_descriptors(0, &keys),
+            Err(Electrum2DescriptorError::ZeroThreshold)
+        ));
+        assert!(matches!(
+            ElectrumExtendedPubKey::sortedmulti_descriptors(1, &keys[..1]),
+            Err(Electrum2DescriptorError::MultisigFewSigners)
+        ));
+        let vpub = ElectrumExtendedPubKey::from_str("vpub5VXaSncXqxLbdmvrC4Y8z9CszPwuEscADoetWhfrxDFzPUbL5nbVtanYDkrVEutkv9n5A5aCcvRC9swbjDKgHjCZ2tAeae8VsBuPbS8KpXv").unwrap();
+        let mixed = [
+            ElectrumExtendedPubKey::new(keys[0].xpub, "sh(wsh".to_string()),
+            ElectrumExtendedPubKey::new(keys[1].xpub, "wsh".to_string()),
+        ];
+        assert!(matches!(
+            ElectrumExtendedPubKey::sortedmulti_descriptors(2, &mixed),
+            Err(Electrum2DescriptorError::MixedScriptKinds(_, _))
+        ));
+        assert!(matches!(
+            ElectrumExtendedPubKey::sortedmulti_descriptors(1, &[vpub.to_taproot().unwrap(), vpub]),
+            Err(Electrum2DescriptorError::SinglesigKindKey(_))
+        ));
+    }
+
     #[test]
     fn test_vpub_to_electrum() {
         let electrum_xpub = ElectrumExtendedPubKey::new(
@@ -224,10 +421,121 @@ mod tests {
         test_first_address("zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs","bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
     }
 
+    #[test]
+    fn test_addresses() {
+        let electrum_xpub = ElectrumExtendedPubKey::from_str("vpub5VXaSncXqxLbdmvrC4Y8z9CszPwuEscADoetWhfrxDFzPUbL5nbVtanYDkrVEutkv9n5A5aCcvRC9swbjDKgHjCZ2tAeae8VsBuPbS8KpXv").unwrap();
+        let descriptors = electrum_xpub.to_descriptors().unwrap();
+        let secp = Secp256k1::verification_only();
+        for (chain, desc) in [(0, descriptors.external), (1, descriptors.change)] {
+            let descriptor: miniscript::Descriptor<DescriptorPublicKey> = desc.parse().unwrap();
+            let addresses = electrum_xpub
+                .addresses(chain, 0..5)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(addresses.len(), 5);
+            for (index, addr) in addresses.iter().enumerate() {
+                let expected = descriptor
+                    .at_derivation_index(index as u32)
+                    .unwrap()
+                    .derived_descriptor(&secp)
+                    .unwrap()
+                    .address(miniscript::bitcoin::Network::Testnet)
+                    .unwrap();
+                assert_eq!(addr.to_string(), expected.to_string());
+                assert_eq!(electrum_xpub.address(chain, index as u32).unwrap(), *addr);
+            }
+        }
+    }
+
+    #[test]
+    fn test_detect_kind() {
+        let xpub = Xpub::from_str("xpub6BosfCnifzxcFwrSzQiqu2DBVTshkCXacvNsWGYJVVhhawA7d4R5WSWGFNbi8Aw6ZRc1brxMyWMzG3DSSSSoekkudhUd9yLb6qx39T9nMdj").unwrap();
+        let (expub, matches) =
+            ElectrumExtendedPubKey::detect_kind(xpub, &["1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA"], 10)
+                .unwrap();
+        assert_eq!(expub.kind(), "pkh");
+        assert_eq!((matches[0].chain, matches[0].index), (0, 0));
+
+        let zpub = ElectrumExtendedPubKey::from_str("zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs").unwrap();
+        let addresses = [
+            zpub.address(1, 3).unwrap().to_string(),
+            zpub.address(0, 7).unwrap().to_string(),
+        ];
+        let addresses = addresses.iter().map(|a| a.as_str()).collect::<Vec<_>>();
+        let (expub, matches) =
+            ElectrumExtendedPubKey::detect_kind(*zpub.xpub(), &addresses, 10).unwrap();
+        assert_eq!(
+            expub.electrum_xpub().unwrap(),
+            zpub.electrum_xpub().unwrap()
+        );
+        assert_eq!(
+            matches
+                .iter()
+                .map(|m| (m.chain, m.index))
+                .collect::<Vec<_>>(),
+            vec![(0, 7), (1, 3)]
+        );
+
+        let repeated = [addresses[0], addresses[1], addresses[0]];
+        let (expub, matches) =
+            ElectrumExtendedPubKey::detect_kind(*zpub.xpub(), &repeated, 10).unwrap();
+        assert_eq!(expub.kind(), "wpkh");
+        assert_eq!(matches.len(), 2);
+
+        assert!(matches!(
+            ElectrumExtendedPubKey::detect_kind(*zpub.xpub(), &addresses, 5),
+            Err(Electrum2DescriptorError::ScriptKindNotDetected)
+        ));
+
+        // a testnet address of the same scriptPubKey doesn't belong to a mainnet key
+        let testnet =
+            Address::from_script(&zpub.script_pubkey(0, 7).unwrap(), Network::Testnet).unwrap();
+        assert!(matches!(
+            ElectrumExtendedPubKey::detect_kind(*zpub.xpub(), &[&testnet.to_string()], 10),
+            Err(Electrum2DescriptorError::AddressParseError(_))
+        ));
+    }
+
+    #[test]
+    fn test_taproot() {
+        let electrum_xpub = ElectrumExtendedPubKey::from_str("vpub5VXaSncXqxLbdmvrC4Y8z9CszPwuEscADoetWhfrxDFzPUbL5nbVtanYDkrVEutkv9n5A5aCcvRC9swbjDKgHjCZ2tAeae8VsBuPbS8KpXv").unwrap();
+        let taproot = electrum_xpub.to_taproot().unwrap();
+        assert_eq!(taproot.kind(), "tr");
+        let descriptors = taproot.to_descriptors().unwrap();
+        assert_eq!(descriptors.external, "tr(tpubD9ZjaMn3rbP1cAVwJy6UcEjFfTLT7W6DbfHdS3Wn48meExtVfKmiH9meWCrSmE9qXLYbGcHC5LxLcdfLZTzwme23qAJoRzRhzbd68dHeyjp/0/*)");
+        assert_eq!(descriptors.change, "tr(tpubD9ZjaMn3rbP1cAVwJy6UcEjFfTLT7W6DbfHdS3Wn48meExtVfKmiH9meWCrSmE9qXLYbGcHC5LxLcdfLZTzwme23qAJoRzRhzbd68dHeyjp/1/*)");
+
+        let descriptor: miniscript::Descriptor<DescriptorPublicKey> =
+            descriptors.change.parse().unwrap();
+        let secp = Secp256k1::verification_only();
+        let expected = descriptor
+            .at_derivation_index(4)
+            .unwrap()
+            .derived_descriptor(&secp)
+            .unwrap()
+            .address(miniscript::bitcoin::Network::Testnet)
+            .unwrap();
+        assert_eq!(
+            taproot.address(1, 4).unwrap().to_string(),
+            expected.to_string()
+        );
+
+        assert!(matches!(
+            taproot.electrum_xpub(),
+            Err(Electrum2DescriptorError::ElectrumUnsupportedScriptKind(kind)) if kind == "tr"
+        ));
+
+        let cosigner = ElectrumExtendedPubKey::from_str("Vpub5mUs4UNPA6T3VAmcTWTJ2nCV2oAEFQqBNQQDH62NQNpdAMSyL2Nd3vZXF6uQfNeiCst7asUapZWM9AKmsYCK1BjUrEVhiVm9M4qnbHvDRDe").unwrap();
+        assert!(matches!(
+            cosigner.to_taproot(),
+            Err(Electrum2DescriptorError::MultisigKindKey(kind)) if kind == "wsh"
+        ));
+    }
+
     fn test_first_address(electrum_xpub: &str, expected_first_address: &str) {
         let electrum_xpub = ElectrumExtendedPubKey::from_str(electrum_xpub).unwrap();
         assert_eq!(electrum_xpub.xpub.network, Network::Bitcoin.into());
-        let descriptors = electrum_xpub.to_descriptors();
+        let descriptors = electrum_xpub.to_descriptors().unwrap();
         let descriptor: miniscript::Descriptor<DescriptorPublicKey> =
             descriptors.external.parse().unwrap();
         let secp = Secp256k1::verification_only();
@@ -240,5 +548,9 @@ mod tests {
             .unwrap()
             .to_string();
         assert_eq!(expected_first_address, first_address);
+        assert_eq!(
+            expected_first_address,
+            electrum_xpub.address(0, 0).unwrap().to_string()
+        );
     }
 }
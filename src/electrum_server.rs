@@ -0,0 +1,171 @@
+//! Minimal client of the electrum server protocol, to find how far the chains of a wallet have been used
+use crate::{electrum_scripthash, AddressSource, Electrum2DescriptorError};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// The protocol version requested in the `server.version` handshake
+const PROTOCOL_VERSION: &str = "1.4";
+
+/// Number of unused addresses after which electrum stops looking for more
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+/// Time to wait for the connection and for every read or write before giving up on an unresponsive server
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The last used index of the receiving and the change chain, `None` if a chain has no history
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UsedIndexes {
+    pub receiving: Option<u32>,
+    pub change: Option<u32>,
+}
+
+impl UsedIndexes {
+    /// The number of addresses per chain to import so that both chains keep `gap_limit` unused addresses after the last used one
+    pub fn import_range(&self, gap_limit: u32) -> u32 {
+        let used = [self.receiving, self.change]
+            .iter()
+            .flatten()
+            .map(|index| index + 1)
+            .max()
+            .unwrap_or(0);
+        used + gap_limit
+    }
+}
+
+/// Connection to an electrum server speaking newline delimited JSON-RPC.
+/// Any stream works, [`ElectrumServer::connect`] opens a TCP and, with the `electrum_server_tls` feature,
+/// [`ElectrumServer::connect_tls`] a TLS connection.
+pub struct ElectrumServer<S: Read + Write> {
+    stream: BufReader<S>,
+    next_id: u64,
+}
+
+/// Open a TCP connection to the first reachable address, failing reads and writes that take longer than `timeout`
+fn connect_tcp<A: ToSocketAddrs>(address: A, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_err = io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to");
+    for address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                return Ok(stream);
+            }
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
+impl ElectrumServer<TcpStream> {
+    /// Connect to a server over plain TCP, usually on port 50001
+    pub fn connect<A: ToSocketAddrs>(
+        address: A,
+        timeout: Duration,
+    ) -> Result<Self, Electrum2DescriptorError> {
+        ElectrumServer::new(connect_tcp(address, timeout)?)
+    }
+}
+
+#[cfg(feature = "electrum_server_tls")]
+impl ElectrumServer<native_tls::TlsStream<TcpStream>> {
+    /// Connect to a server over TLS, usually on port 50002.
+    /// The certificate has to be valid for `host`, self-signed certificates are rejected.
+    pub fn connect_tls(
+        host: &str,
+        port: u16,
+        timeout: Duration,
+    ) -> Result<Self, Electrum2DescriptorError> {
+        let stream = connect_tcp((host, port), timeout)?;
+        let stream = native_tls::TlsConnector::new()?
+            .connect(host, stream)
+            .map_err(|err| match err {
+                native_tls::HandshakeError::Failure(err) => err.into(),
+                native_tls::HandshakeError::WouldBlock(_) => Electrum2DescriptorError::from(
+                    io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out"),
+                ),
+            })?;
+        ElectrumServer::new(stream)
+    }
+}
+
+impl<S: Read + Write> ElectrumServer<S> {
+    /// Negotiate the protocol version over an established connection
+    pub fn new(stream: S) -> Result<Self, Electrum2DescriptorError> {
+        let mut server = ElectrumServer {
+            stream: BufReader::new(stream),
+            next_id: 0,
+        };
+        server.call(
+            "server.version",
+            json!([env!("CARGO_PKG_NAME"), PROTOCOL_VERSION]),
+        )?;
+        Ok(server)
+    }
+
+    /// Send a request and wait for its response, skipping notifications in between
+    fn call(&mut self, method: &str, params: Value) -> Result<Value, Electrum2DescriptorError> {
+        self.next_id += 1;
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id,
+            "method": method,
+            "params": params,
+        });
+        let stream = self.stream.get_mut();
+        stream.write_all(format!("{}\n", request).as_bytes())?;
+        stream.flush()?;
+
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line)? == 0 {
+                return Err(Electrum2DescriptorError::ElectrumServer(
+                    "connection closed".to_string(),
+                ));
+            }
+            let mut response: Value = serde_json::from_str(&line)?;
+            if response["id"] != self.next_id {
+                continue;
+            }
+            if !response["error"].is_null() {
+                return Err(Electrum2DescriptorError::ElectrumServer(
+                    response["error"].to_string(),
+                ));
+            }
+            return Ok(response["result"].take());
+        }
+    }
+
+    /// Whether the server knows any transaction of the scriptPubKey with the given electrum scripthash
+    pub fn is_used(&mut self, scripthash: &str) -> Result<bool, Electrum2DescriptorError> {
+        let history = self.call("blockchain.scripthash.get_history", json!([scripthash]))?;
+        match history.as_array() {
+            Some(history) => Ok(!history.is_empty()),
+            None => Err(Electrum2DescriptorError::ElectrumServer(format!(
+                "unexpected history: {}",
+                history
+            ))),
+        }
+    }
+
+    /// Walk both chains of the key or wallet until `gap_limit` consecutive addresses have no history
+    pub fn scan(
+        &mut self,
+        source: &dyn AddressSource,
+        gap_limit: u32,
+    ) -> Result<UsedIndexes, Electrum2DescriptorError> {
+        let mut used = UsedIndexes::default();
+        for (chain, last_used) in [(0, &mut used.receiving), (1, &mut used.change)] {
+            let mut index = 0;
+            while index - last_used.map_or(0, |last| last + 1) < gap_limit {
+                let scripthash = electrum_scripthash(&source.script_pubkey(chain, index)?);
+                if self.is_used(&scripthash)? {
+                    *last_used = Some(index);
+                }
+                index += 1;
+            }
+        }
+        Ok(used)
+    }
+}
@@ -1,11 +1,28 @@
 use crate::{
-    Descriptors, Electrum2DescriptorError, ElectrumExtendedKey, ElectrumExtendedPrivKey,
-    ElectrumExtendedPubKey,
+    address, AddressSource, Descriptors, Electrum2DescriptorError, ElectrumExtendedKey,
+    ElectrumExtendedPrivKey, ElectrumExtendedPubKey, KeyPolicy, ScriptHash,
 };
-use bitcoin::bip32::{Xpriv, Xpub};
+use bitcoin::bip32::{DerivationPath, Fingerprint, Xpriv, Xpub};
+use bitcoin::{Address, Network, NetworkKind, ScriptBuf};
 use regex::Regex;
 use serde::{de, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
-use std::{fmt, io::BufReader, path::Path, str::FromStr, string::ToString};
+use std::{
+    collections::BTreeMap, fmt, io::BufReader, ops::Range, path::Path, str::FromStr,
+    string::ToString,
+};
+
+/// Regular expression of a descriptor key with optional origin: `[fingerprint/derivation]xkey`
+pub(crate) const KEY_EXPRESSION: &str =
+    r#"(?:\[[0-9a-fA-F]{8}(?:/[0-9]+['h]?)*\])?[tx]p(?:ub|rv)[0-9A-Za-z]+"#;
+/// Regular expression of the derivation steps between a descriptor key and the `/0/*` of the chain
+const DERIVATION_STEPS: &str = r#"(?:/[0-9]+['h]?)*"#;
+
+/// Number of receiving addresses electrum generates ahead, its default gap limit
+pub const DEFAULT_RECEIVING_ADDRESSES: u32 = 20;
+/// Number of change addresses electrum generates ahead
+pub const DEFAULT_CHANGE_ADDRESSES: u32 = 10;
+/// Maximum number of cosigners of an electrum multisig wallet
+pub const MAX_COSIGNERS: usize = 15;
 
 /// Representation of an electrum wallet file. Has custom serialization and de-serialization routines to more accurately represent what we need, and the electrum wallet file format.
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -13,30 +30,34 @@ pub struct ElectrumWalletFile {
     addresses: Addresses,
     wallet_type: WalletType,
     keystores: Vec<Keystore>,
+    history: History,
 }
 
 impl ElectrumWalletFile {
-    /// Construct a wallet
+    /// Construct a wallet. The addresses section is filled with electrum's default number of addresses.
     pub fn new(
         keystores: &[Keystore],
         min_signatures: u8,
     ) -> Result<Self, Electrum2DescriptorError> {
-        let wallet = if keystores.len() == 1 {
+        let mut wallet = if keystores.len() == 1 {
             ElectrumWalletFile {
                 addresses: Addresses::new(),
                 wallet_type: WalletType::Standard,
                 keystores: keystores.to_vec(),
+                history: History::default(),
             }
-        } else if keystores.len() >= 255 {
+        } else if keystores.len() > MAX_COSIGNERS {
             return Err(Electrum2DescriptorError::TooManyKeyStores(keystores.len()));
         } else {
             ElectrumWalletFile {
                 addresses: Addresses::new(),
                 wallet_type: WalletType::Multisig(min_signatures, keystores.len() as u8),
                 keystores: keystores.to_vec(),
+                history: History::default(),
             }
         };
         wallet.validate()?;
+        wallet.generate_addresses(DEFAULT_RECEIVING_ADDRESSES, DEFAULT_CHANGE_ADDRESSES)?;
         Ok(wallet)
     }
 
@@ -55,6 +76,35 @@ impl ElectrumWalletFile {
         &self.keystores
     }
 
+    /// Getter for the transaction history
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// The earliest confirmed transaction of the wallet, `None` if the wallet file knows of none.
+    /// The time is only known if electrum verified a transaction of that block.
+    pub fn birthday(&self) -> Option<Birthday> {
+        let height = self
+            .history
+            .addr_history
+            .values()
+            .flatten()
+            .map(|(_txid, height)| *height)
+            .chain(self.history.verified_tx.values().map(|verified| verified.0))
+            .filter(|height| *height > 0)
+            .min()?;
+        Some(Birthday {
+            height: height as u32,
+            timestamp: self
+                .history
+                .verified_tx
+                .values()
+                .filter(|verified| verified.0 == height)
+                .map(|verified| verified.1)
+                .min(),
+        })
+    }
+
     /// Parse an electrum wallet file
     pub fn from_file(wallet_file: &Path) -> Result<Self, Electrum2DescriptorError> {
         let file = std::fs::File::open(wallet_file)?;
@@ -70,31 +120,70 @@ impl ElectrumWalletFile {
     }
 
     /// Construct from an output descriptor. Only the external descriptor is needed, the change descriptor is implied.
+    /// The addresses section is filled with electrum's default number of addresses.
+    /// Derivation steps before `/0/*` are applied to the key and recorded in its origin if the key has one or is a master key, e.g. `wpkh(xprv/84'/0'/0'/0/*)`.
     pub fn from_descriptor(desc: &str) -> Result<Self, Electrum2DescriptorError> {
-        let wallet = if desc.contains("(sortedmulti(") {
+        if desc.contains("*'") || desc.contains("*h") {
+            return Err(Electrum2DescriptorError::HardenedWildcard(desc.to_string()));
+        }
+        let mut wallet = if desc.contains("(sortedmulti(") {
             ElectrumWalletFile::from_descriptor_multisig(desc)
         } else {
             ElectrumWalletFile::from_descriptor_singlesig(desc)
         }?;
         wallet.validate()?;
+        wallet.generate_addresses(DEFAULT_RECEIVING_ADDRESSES, DEFAULT_CHANGE_ADDRESSES)?;
+        Ok(wallet)
+    }
+
+    /// Construct from an external and a change descriptor, e.g. as exported by other wallet software.
+    /// Electrum derives change addresses from chain 1, so the change descriptor must use the keys and script of the external one with `/1/*`.
+    pub fn from_descriptors(
+        external: &str,
+        change: &str,
+    ) -> Result<Self, Electrum2DescriptorError> {
+        let mismatch = || {
+            Electrum2DescriptorError::ChangeDescriptorMismatch(
+                external.to_string(),
+                change.to_string(),
+            )
+        };
+        if change.matches("/*").count() != change.matches("/1/*").count() {
+            return Err(mismatch());
+        }
+        let wallet = ElectrumWalletFile::from_descriptor(external)?;
+        let change_wallet = ElectrumWalletFile::from_descriptor(&change.replace("/1/*", "/0/*"))?;
+        let xpubs = |w: &ElectrumWalletFile| {
+            w.keystores
+                .iter()
+                .map(|ks| ks.xpub.clone())
+                .collect::<Vec<_>>()
+        };
+        if wallet.wallet_type != change_wallet.wallet_type
+            || xpubs(&wallet) != xpubs(&change_wallet)
+        {
+            return Err(mismatch());
+        }
         Ok(wallet)
     }
 
     /// Construct from a single signature output descriptor. Only the external descriptor is needed, the change descriptor is implied.
     fn from_descriptor_singlesig(desc: &str) -> Result<Self, Electrum2DescriptorError> {
-        let re =
-            Regex::new(r#"(pkh|sh\(wpkh|sh\(wsh|wpkh|wsh)\((([tx]p(ub|rv)[0-9A-Za-z]+)/0/\*)\)+"#)?;
+        let re = Regex::new(&format!(
+            r#"(pkh|sh\(wpkh|sh\(wsh|wpkh|wsh|tr)\(({}{})/0/\*\)+"#,
+            KEY_EXPRESSION, DERIVATION_STEPS
+        ))?;
         let captures = re.captures(desc).map(|captures| {
             captures
                 .iter()
                 .skip(1)
-                .take(3)
+                .take(2)
                 .flatten()
                 .map(|c| c.as_str())
                 .collect::<Vec<_>>()
         });
         let keystore = match captures.as_deref() {
-            Some([kind, _, xkey]) => Keystore::new(kind, xkey)?,
+            Some([kind, xkey]) => Keystore::from_key_expression(kind, xkey)?,
             _ => {
                 return Err(Electrum2DescriptorError::UnknownDescriptorFormat(format!(
                     "{:?}",
@@ -107,14 +196,16 @@ impl ElectrumWalletFile {
             addresses: Addresses::new(),
             keystores: vec![keystore],
             wallet_type: WalletType::Standard,
+            history: History::default(),
         })
     }
 
     /// Construct from a multisig output descriptor. Only the external descriptor is needed, the change descriptor is implied.
     fn from_descriptor_multisig(desc: &str) -> Result<Self, Electrum2DescriptorError> {
-        let re = Regex::new(
-            r#"(sh|sh\(wsh|wsh)\(sortedmulti\((\d),([tx]p(ub|rv)[0-9A-Za-z]+/0/\*,?)+\)+"#,
-        )?;
+        let re = Regex::new(&format!(
+            r#"(sh|sh\(wsh|wsh)\(sortedmulti\((\d+),({}{}/0/\*,?)+\)+"#,
+            KEY_EXPRESSION, DERIVATION_STEPS
+        ))?;
         let captures = re.captures(desc).map(|captures| {
             captures
                 .iter()
@@ -135,20 +226,27 @@ impl ElectrumWalletFile {
                     ))
                 }
             };
-            let re = Regex::new(r#"[tx]p[ur][bv][0-9A-Za-z]+"#)?;
+            let re = Regex::new(&format!(r#"({}{})/0/\*"#, KEY_EXPRESSION, DERIVATION_STEPS))?;
             let keystores = re
                 .captures_iter(desc)
-                .map(|cap| Keystore::new(kind, &cap[0]))
+                .map(|cap| Keystore::from_key_expression(kind, &cap[1]))
                 .collect::<Result<Vec<Keystore>, _>>()?;
             let y = keystores.len();
             if y < 2 {
                 return Err(Electrum2DescriptorError::MultisigFewSigners);
             }
+            if y > MAX_COSIGNERS {
+                return Err(Electrum2DescriptorError::TooManyKeyStores(y));
+            }
+            let x = x
+                .parse()
+                .map_err(|_| Electrum2DescriptorError::UnknownDescriptorFormat(x.to_string()))?;
 
             Ok(ElectrumWalletFile {
                 addresses: Addresses::new(),
                 keystores,
-                wallet_type: WalletType::Multisig(x.parse().unwrap(), y as u8),
+                wallet_type: WalletType::Multisig(x, y as u8),
+                history: History::default(),
             })
         } else {
             Err(Electrum2DescriptorError::UnknownDescriptorFormat(format!(
@@ -160,47 +258,127 @@ impl ElectrumWalletFile {
 
     /// Generate output descriptors matching the electrum wallet
     pub fn to_descriptors(&self) -> Result<Descriptors, Electrum2DescriptorError> {
-        match self.wallet_type {
-            WalletType::Standard => {
-                let exkey = self.keystores[0].get_xkey()?;
-                let desc_ext = exkey.kind().to_string() + "(" + &exkey.xkey_str() + "/0/*)";
-                let desc_chg = exkey.kind().to_string() + "(" + &exkey.xkey_str() + "/1/*)";
+        self.to_descriptors_with_policy(KeyPolicy::PrivateWhereAvailable, false)
+    }
 
-                Ok(Descriptors {
-                    external: desc_ext,
-                    change: desc_chg,
-                })
-            }
+    /// Generate output descriptors matching the electrum wallet, with the key origins `[fingerprint/derivation]` known to the keystores
+    pub fn to_descriptors_with_origins(&self) -> Result<Descriptors, Electrum2DescriptorError> {
+        self.to_descriptors_with_policy(KeyPolicy::PrivateWhereAvailable, true)
+    }
+
+    /// Generate output descriptors with the keys selected by `policy`, optionally with the key origins of the keystores
+    pub fn to_descriptors_with_policy(
+        &self,
+        policy: KeyPolicy,
+        with_origins: bool,
+    ) -> Result<Descriptors, Electrum2DescriptorError> {
+        let xkeys = self
+            .keystores
+            .iter()
+            .enumerate()
+            .map(|(i, ks)| {
+                let exkey = ks.get_xkey_with_policy(policy, i)?;
+                let origin = match ks.origin()? {
+                    Some((fingerprint, path)) if with_origins => format_origin(&fingerprint, &path),
+                    _ => String::new(),
+                };
+                Ok((exkey.kind().to_string(), origin + &exkey.xkey_str()))
+            })
+            .collect::<Result<Vec<_>, Electrum2DescriptorError>>()?;
+        match self.wallet_type {
+            WalletType::Standard => Descriptors::singlesig(&xkeys[0].0, &xkeys[0].1),
             WalletType::Multisig(x, _y) => {
-                let xkeys = self
-                    .keystores
+                let keys = xkeys
                     .iter()
-                    .map(|ks| ks.get_xkey())
-                    .collect::<Result<Vec<Box<dyn ElectrumExtendedKey>>, _>>()?;
-                let prefix = match xkeys[0].kind() as &str {
-                    "pkh" => "sh",
-                    kind => kind,
-                }
-                .to_string();
-                let prefix = format!("{}(sortedmulti({}", prefix, x);
-
-                let mut desc = xkeys.iter().fold(prefix, |acc, exkey| {
-                    acc + &(",".to_string() + &exkey.xkey_str() + "/0/*")
-                });
-                desc += "))";
-                let opening = desc.matches('(').count();
-                let closing = desc.matches(')').count();
-                if opening > closing {
-                    desc += ")"
-                };
-                let desc_chg = desc.replace("/0/*", "/1/*");
+                    .map(|(_kind, xkey)| xkey.clone())
+                    .collect::<Vec<_>>();
+                Ok(Descriptors::sortedmulti(&xkeys[0].0, x, &keys))
+            }
+        }
+    }
 
-                Ok(Descriptors {
-                    external: desc,
-                    change: desc_chg,
-                })
+    /// Generate taproot output descriptors `tr(xkey/0/*)` from the key of a single signature wallet selected by `policy`.
+    /// This is a conversion to a different wallet, as electrum has no taproot support, so the addresses differ from the electrum ones.
+    pub fn to_taproot_descriptors(
+        &self,
+        policy: KeyPolicy,
+    ) -> Result<Descriptors, Electrum2DescriptorError> {
+        match self.wallet_type {
+            WalletType::Standard => {
+                let xkey = self.keystores[0]
+                    .get_xkey_with_policy(policy, 0)?
+                    .xkey_str();
+                let [external, change] = [0, 1].map(|i| format!("tr({}/{}/*)", xkey, i));
+                Ok(Descriptors { external, change })
+            }
+            WalletType::Multisig(_x, _y) => Err(Electrum2DescriptorError::TaprootMultisig),
+        }
+    }
+
+    /// Derive every receiving and change address from the keystores and compare them with the addresses section.
+    /// Returns the addresses that don't match, so an empty result proves the conversion to be correct.
+    pub fn verify_addresses(&self) -> Result<Vec<AddressMismatch>, Electrum2DescriptorError> {
+        let network = self.network()?;
+        let mut mismatches = Vec::new();
+        for (chain, addresses) in [(0, &self.addresses.receiving), (1, &self.addresses.change)] {
+            for (index, found) in addresses.iter().enumerate() {
+                let index = index as u32;
+                let script = self.script_pubkey(chain, index)?;
+                let matching = Address::from_str(found)
+                    .map(|addr| addr.assume_checked().script_pubkey() == script)
+                    .unwrap_or(false);
+                if !matching {
+                    mismatches.push(AddressMismatch {
+                        chain,
+                        index,
+                        expected: Address::from_script(&script, network)?.to_string(),
+                        found: found.clone(),
+                    });
+                }
             }
         }
+        Ok(mismatches)
+    }
+
+    /// Replace the addresses section with the first `receiving` and `change` addresses derived from the keystores
+    pub fn generate_addresses(
+        &mut self,
+        receiving: u32,
+        change: u32,
+    ) -> Result<(), Electrum2DescriptorError> {
+        let network = self.network()?;
+        let [receiving, change] = [(0, receiving), (1, change)].map(|(chain, count)| {
+            (0..count)
+                .map(|index| {
+                    let script = self.script_pubkey(chain, index)?;
+                    Ok(Address::from_script(&script, network)?.to_string())
+                })
+                .collect::<Result<Vec<_>, Electrum2DescriptorError>>()
+        });
+        self.addresses = Addresses {
+            change: change?,
+            receiving: receiving?,
+        };
+        Ok(())
+    }
+
+    /// Returns address, scriptPubKey and electrum scripthash of `chain` for the given range of indexes
+    pub fn scripthashes(
+        &self,
+        chain: u32,
+        indexes: Range<u32>,
+    ) -> Result<Vec<ScriptHash>, Electrum2DescriptorError> {
+        address::scripthashes(self, self.network()?, chain, indexes)
+    }
+
+    /// The network the keys of this wallet belong to
+    pub(crate) fn network(&self) -> Result<Network, Electrum2DescriptorError> {
+        let keystore = self
+            .keystores
+            .first()
+            .ok_or(Electrum2DescriptorError::WrongNumberOfKeyStores(0, 1))?;
+        let xpub = ElectrumExtendedPubKey::from_str(&keystore.xpub)?;
+        Ok(address::network(xpub.xpub().network))
     }
 
     /// validate the internal structure
@@ -217,7 +395,13 @@ impl ElectrumWalletFile {
             ));
         }
 
-        if let WalletType::Multisig(x, _y) = self.wallet_type {
+        if let WalletType::Multisig(x, y) = self.wallet_type {
+            if y as usize > MAX_COSIGNERS {
+                return Err(Electrum2DescriptorError::TooManyKeyStores(y.into()));
+            }
+            if x == 0 {
+                return Err(Electrum2DescriptorError::ZeroThreshold);
+            }
             if x as usize > expected_keystores {
                 return Err(Electrum2DescriptorError::NumberSignaturesKeyStores(
                     x,
@@ -226,10 +410,60 @@ impl ElectrumWalletFile {
             }
         }
 
+        let expubs = self
+            .keystores
+            .iter()
+            .map(|ks| ElectrumExtendedPubKey::from_str(&ks.xpub))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (i, (keystore, expub)) in self.keystores.iter().zip(&expubs).enumerate() {
+            if expub.kind() != expubs[0].kind() {
+                return Err(Electrum2DescriptorError::MixedScriptKinds(
+                    expubs[0].kind().to_string(),
+                    expub.kind().to_string(),
+                ));
+            }
+            if expub.xpub().network != expubs[0].xpub().network {
+                return Err(Electrum2DescriptorError::MixedNetworks);
+            }
+            if let Some(j) = expubs[..i].iter().position(|e| e.xpub() == expub.xpub()) {
+                return Err(Electrum2DescriptorError::DuplicateCosigner(j, i));
+            }
+            if let Some(xprv) = &keystore.xprv {
+                let xprv = *ElectrumExtendedPrivKey::from_str(xprv)?.xprv();
+                let secp = bitcoin::secp256k1::Secp256k1::new();
+                if Xpub::from_priv(&secp, &xprv) != *expub.xpub() {
+                    return Err(Electrum2DescriptorError::KeystoreKeyMismatch(i));
+                }
+            }
+        }
+
         Ok(())
     }
 }
 
+impl AddressSource for ElectrumWalletFile {
+    fn script_pubkey(&self, chain: u32, index: u32) -> Result<ScriptBuf, Electrum2DescriptorError> {
+        let expubs = self
+            .keystores
+            .iter()
+            .map(|ks| ElectrumExtendedPubKey::from_str(&ks.xpub))
+            .collect::<Result<Vec<_>, _>>()?;
+        match self.wallet_type {
+            WalletType::Standard => {
+                address::singlesig_script(expubs[0].kind(), expubs[0].xpub(), chain, index)
+            }
+            WalletType::Multisig(x, _y) => {
+                let xpubs = expubs.iter().map(|e| *e.xpub()).collect::<Vec<_>>();
+                address::multisig_script(expubs[0].kind(), x, &xpubs, chain, index)
+            }
+        }
+    }
+
+    fn network_kind(&self) -> Result<NetworkKind, Electrum2DescriptorError> {
+        Ok(self.network()?.into())
+    }
+}
+
 impl FromStr for ElectrumWalletFile {
     type Err = Electrum2DescriptorError;
 
@@ -256,6 +490,12 @@ impl Serialize for ElectrumWalletFile {
         let mut map = serializer.serialize_map(None)?;
         map.serialize_entry("addresses", &self.addresses)?;
         map.serialize_entry("wallet_type", &self.wallet_type)?;
+        if !self.history.addr_history.is_empty() {
+            map.serialize_entry("addr_history", &self.history.addr_history)?;
+        }
+        if !self.history.verified_tx.is_empty() {
+            map.serialize_entry("verified_tx3", &self.history.verified_tx)?;
+        }
         match self.wallet_type {
             WalletType::Standard => {
                 map.serialize_entry("keystore", &self.keystores[0])?;
@@ -283,7 +523,10 @@ impl<'de> Deserialize<'de> for ElectrumWalletFile {
         enum Field {
             Addrs,
             Keyst,
+            Cosigner(usize),
             WalTyp,
+            AddrHist,
+            VerifTx,
             Ignore,
         }
 
@@ -317,10 +560,14 @@ impl<'de> Deserialize<'de> for ElectrumWalletFile {
                                 .collect::<Vec<_>>()
                         });
                         match captures.as_deref() {
-                            Some(["x", _i, "/"]) => Ok(Field::Keyst),
+                            Some(["x", i, "/"]) => i.parse().map(Field::Cosigner).map_err(|_| {
+                                E::custom(format!("invalid keystore index: {}", value))
+                            }),
                             Some(["keystore"]) => Ok(Field::Keyst),
                             Some(["addresses"]) => Ok(Field::Addrs),
                             Some(["wallet_type"]) => Ok(Field::WalTyp),
+                            Some(["addr_history"]) => Ok(Field::AddrHist),
+                            Some(["verified_tx3"]) => Ok(Field::VerifTx),
                             _ => Ok(Field::Ignore),
                         }
                     }
@@ -345,7 +592,9 @@ impl<'de> Deserialize<'de> for ElectrumWalletFile {
             {
                 let mut addresses = Addresses::new();
                 let mut keystores = Vec::new();
+                let mut cosigners = BTreeMap::new();
                 let mut wallet_type = WalletType::Standard;
+                let mut history = History::default();
 
                 while let Some(key) = map.next_key()? {
                     match key {
@@ -355,19 +604,41 @@ impl<'de> Deserialize<'de> for ElectrumWalletFile {
                         Field::Keyst => {
                             keystores.push(map.next_value()?);
                         }
+                        Field::Cosigner(i) => {
+                            if cosigners.insert(i, map.next_value()?).is_some() {
+                                return Err(de::Error::custom(
+                                    Electrum2DescriptorError::DuplicateKeystoreIndex(i),
+                                ));
+                            }
+                        }
                         Field::WalTyp => {
                             wallet_type = map.next_value()?;
                         }
+                        Field::AddrHist => {
+                            history.addr_history = map.next_value()?;
+                        }
+                        Field::VerifTx => {
+                            history.verified_tx = map.next_value()?;
+                        }
                         Field::Ignore => {
                             let _ignore = map.next_value::<de::IgnoredAny>()?;
                         }
                     }
                 }
 
+                // Cosigners are ordered by their index, x10/ comes after x9/
+                if let Some(missing) = (1..=cosigners.len()).find(|i| !cosigners.contains_key(i)) {
+                    return Err(de::Error::custom(
+                        Electrum2DescriptorError::MissingKeystoreIndex(missing),
+                    ));
+                }
+                keystores.extend(cosigners.into_values());
+
                 let wallet = ElectrumWalletFile {
                     addresses,
                     keystores,
                     wallet_type,
+                    history,
                 };
                 wallet.validate().map_err(de::Error::custom)?;
                 Ok(wallet)
@@ -379,6 +650,7 @@ impl<'de> Deserialize<'de> for ElectrumWalletFile {
             "addr_history",
             "channel_backups",
             "keystore",
+            "verified_tx3",
             "wallet_type",
             "x1/",
             "x2/",
@@ -404,18 +676,57 @@ impl Addresses {
     }
 }
 
+/// Representation of the transaction history sections of an electrum wallet file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct History {
+    /// `addr_history`: txid and block height of the transactions of each address, the height is 0 or below if unconfirmed
+    pub addr_history: BTreeMap<String, Vec<(String, i64)>>,
+    /// `verified_tx3`: the transactions electrum verified against the block headers, by txid
+    pub verified_tx: BTreeMap<String, VerifiedTx>,
+}
+
+/// A verified transaction: block height, block time, position in the block and, in newer versions of electrum, the block hash
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct VerifiedTx(
+    pub i64,
+    pub u64,
+    pub u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")] pub Option<String>,
+);
+
+/// Height and unix time of the first confirmed transaction of a wallet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Birthday {
+    pub height: u32,
+    /// Only known if electrum verified the transaction
+    pub timestamp: Option<u64>,
+}
+
+/// An address of the addresses section which doesn't match the one derived from the keystores
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AddressMismatch {
+    pub chain: u32,
+    pub index: u32,
+    pub expected: String,
+    pub found: String,
+}
+
 /// Representation of a keystore section of an electrum wallet file. Can be single sig "keystore" or multisig "x1/" "x2/" ...
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct Keystore {
     #[serde(default = "Keystore::default_type")]
     pub r#type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derivation: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_fingerprint: Option<String>,
     pub xprv: Option<String>,
     pub xpub: String,
 }
 
 impl Keystore {
-    /// Construct a Keystore from script kind and xpub or xprv
-    fn new(kind: &str, xkey: &str) -> Result<Self, Electrum2DescriptorError> {
+    /// Construct a Keystore from script kind and BIP32 xpub or xprv
+    pub fn new(kind: &str, xkey: &str) -> Result<Self, Electrum2DescriptorError> {
         let xprv = Xpriv::from_str(xkey);
         let exprv = if let Ok(xprv) = xprv {
             Some(ElectrumExtendedPrivKey::new(xprv, kind.to_string()).electrum_xprv()?)
@@ -433,11 +744,45 @@ impl Keystore {
 
         Ok(Keystore {
             r#type: Keystore::default_type(),
+            derivation: None,
+            root_fingerprint: None,
             xprv: exprv,
             xpub: expub,
         })
     }
 
+    /// Construct a Keystore from script kind and a descriptor key with optional origin `[fingerprint/derivation]xkey`
+    pub fn from_key_expression(kind: &str, key: &str) -> Result<Self, Electrum2DescriptorError> {
+        let (origin, xkey) = account_key(key)?;
+        let keystore = Keystore::new(kind, &xkey)?;
+        Ok(match origin {
+            Some((fingerprint, path)) => keystore.with_origin(fingerprint, &path),
+            None => keystore,
+        })
+    }
+
+    /// Set the fingerprint of the master key and the derivation path from it to this keystore's key
+    pub fn with_origin(mut self, fingerprint: Fingerprint, path: &DerivationPath) -> Self {
+        self.root_fingerprint = Some(fingerprint.to_string());
+        self.derivation = Some(format_path(path));
+        self
+    }
+
+    /// Get the fingerprint of the master key and the derivation path, if both are known
+    pub fn origin(
+        &self,
+    ) -> Result<Option<(Fingerprint, DerivationPath)>, Electrum2DescriptorError> {
+        match (&self.root_fingerprint, &self.derivation) {
+            (Some(fingerprint), Some(derivation)) => {
+                let fingerprint = Fingerprint::from_str(fingerprint).map_err(|_| {
+                    Electrum2DescriptorError::InvalidKeyOrigin(fingerprint.to_string())
+                })?;
+                Ok(Some((fingerprint, DerivationPath::from_str(derivation)?)))
+            }
+            _ => Ok(None),
+        }
+    }
+
     /// Get the xprv if available or else the xpub.
     fn get_xkey(&self) -> Result<Box<dyn ElectrumExtendedKey>, Electrum2DescriptorError> {
         if let Some(xprv) = &self.xprv {
@@ -449,12 +794,102 @@ impl Keystore {
         Ok(Box::new(expub))
     }
 
+    /// Get the key selected by `policy`, where `index` is the keystore's position reported if its xprv is required but missing
+    fn get_xkey_with_policy(
+        &self,
+        policy: KeyPolicy,
+        index: usize,
+    ) -> Result<Box<dyn ElectrumExtendedKey>, Electrum2DescriptorError> {
+        match policy {
+            KeyPolicy::PublicOnly => Ok(Box::new(ElectrumExtendedPubKey::from_str(&self.xpub)?)),
+            KeyPolicy::PrivateWhereAvailable => self.get_xkey(),
+            KeyPolicy::RequirePrivate => {
+                let xprv = self
+                    .xprv
+                    .as_ref()
+                    .ok_or(Electrum2DescriptorError::MissingPrivateKey(index))?;
+                Ok(Box::new(ElectrumExtendedPrivKey::from_str(xprv)?))
+            }
+        }
+    }
+
     /// Default keystore type to use if nothing else was specified
     fn default_type() -> String {
         "bip32".to_string()
     }
 }
 
+/// Fingerprint of the master key and derivation path from it to a key
+pub type KeyOrigin = (Fingerprint, DerivationPath);
+
+/// Split a descriptor key `[fingerprint/derivation]xkey` into its optional origin and the extended key
+pub(crate) fn split_key_origin(
+    key: &str,
+) -> Result<(Option<KeyOrigin>, &str), Electrum2DescriptorError> {
+    match key.strip_prefix('[').and_then(|k| k.split_once(']')) {
+        Some((origin, xkey)) => {
+            let (fingerprint, path) = origin.split_once('/').unwrap_or((origin, ""));
+            let fingerprint = Fingerprint::from_str(fingerprint)
+                .map_err(|_| Electrum2DescriptorError::InvalidKeyOrigin(origin.to_string()))?;
+            Ok((Some((fingerprint, DerivationPath::from_str(path)?)), xkey))
+        }
+        None => Ok((None, key)),
+    }
+}
+
+/// Split a descriptor key like `[fingerprint/derivation]xprv/84'/0'/0'` into the key at the end of the derivation steps and its origin.
+/// Without steps the key and its optional origin are returned as they are.
+/// Without origin only the steps from a master key are known to be an origin, those from any deeper key are dropped.
+pub(crate) fn account_key(
+    key: &str,
+) -> Result<(Option<KeyOrigin>, String), Electrum2DescriptorError> {
+    let (origin, key) = split_key_origin(key)?;
+    let (xkey, steps) = match key.split_once('/') {
+        Some((xkey, steps)) => (xkey, DerivationPath::from_str(steps)?),
+        None => return Ok((origin, key.to_string())),
+    };
+    let secp = bitcoin::secp256k1::Secp256k1::new();
+    let (depth, fingerprint, xkey) = match Xpriv::from_str(xkey) {
+        Ok(xprv) => (
+            xprv.depth,
+            xprv.fingerprint(&secp),
+            xprv.derive_priv(&secp, &steps)?.to_string(),
+        ),
+        Err(_) => {
+            let xpub = Xpub::from_str(xkey)?;
+            (
+                xpub.depth,
+                xpub.fingerprint(),
+                xpub.derive_pub(&secp, &steps)?.to_string(),
+            )
+        }
+    };
+    let origin = match origin {
+        Some((fingerprint, path)) => Some((fingerprint, path.extend(&steps))),
+        None if depth == 0 => Some((fingerprint, steps)),
+        None => None,
+    };
+    Ok((origin, xkey))
+}
+
+/// Format a derivation path from the master key: `m/84'/0'/0'`, or `m` for the master key itself
+pub(crate) fn format_path(path: &DerivationPath) -> String {
+    if path.is_empty() {
+        "m".to_string()
+    } else {
+        format!("m/{}", path)
+    }
+}
+
+/// Format a key origin as used in descriptors: `[fingerprint/derivation]`
+pub(crate) fn format_origin(fingerprint: &Fingerprint, path: &DerivationPath) -> String {
+    if path.is_empty() {
+        format!("[{}]", fingerprint)
+    } else {
+        format!("[{}/{}]", fingerprint, path)
+    }
+}
+
 /// Representation of the wallet_type section of an electrum wallet file. Has custom serialization and de-serialization implementatoin.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum WalletType {
@@ -484,7 +919,12 @@ impl FromStr for WalletType {
         });
         match captures.as_deref() {
             Some(["standard"]) => Ok(WalletType::Standard),
-            Some([x, "of", y]) => Ok(WalletType::Multisig(x.parse().unwrap(), y.parse().unwrap())),
+            Some([x, "of", y]) => match (x.parse(), y.parse()) {
+                (Ok(x), Ok(y)) => Ok(WalletType::Multisig(x, y)),
+                _ => Err(Electrum2DescriptorError::UnknownWalletType(
+                    wallet_type.to_string(),
+                )),
+            },
             _ => Err(Electrum2DescriptorError::UnknownWalletType(
                 wallet_type.to_string(),
             )),
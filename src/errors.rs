@@ -1,4 +1,4 @@
-use bitcoin::{base58, bip32, secp256k1};
+use bitcoin::{address, base58, bip32, secp256k1};
 #[cfg(feature = "wallet_file")]
 use serde_json::Error as SerdeError;
 use std::io;
@@ -19,9 +19,19 @@ pub enum Electrum2DescriptorError {
     Secp256k1Error(#[from] secp256k1::Error),
     #[error(transparent)]
     Bip32Error(#[from] bip32::Error),
+    #[error(transparent)]
+    AddressFromScriptError(#[from] address::FromScriptError),
+    #[error(transparent)]
+    AddressParseError(#[from] address::ParseError),
     #[cfg(feature = "wallet_file")]
     #[error(transparent)]
     RegexError(#[from] regex::Error),
+    #[cfg(feature = "bdk")]
+    #[error(transparent)]
+    BdkDescriptorError(#[from] bdk_wallet::descriptor::DescriptorError),
+    #[cfg(feature = "electrum_server_tls")]
+    #[error(transparent)]
+    TlsError(#[from] native_tls::Error),
 
     #[error("Unknown type")]
     UnknownType,
@@ -35,12 +45,70 @@ pub enum Electrum2DescriptorError {
     WrongNumberOfKeyStores(usize, usize),
     #[error("Minimum number of signatures {0} must not be greater than keystores {1}")]
     NumberSignaturesKeyStores(u8, usize),
-    #[error("keystore sizes above 255 are not currently supported. {0}")]
+    #[error("Electrum supports at most 15 cosigners, not {0}")]
     TooManyKeyStores(usize),
+    #[error("Keystore x{0}/ appears more than once")]
+    DuplicateKeystoreIndex(usize),
+    #[error("Keystore x{0}/ is missing")]
+    MissingKeystoreIndex(usize),
     #[error("Unknown script kind: {0}")]
     UnknownScriptKind(String),
+    #[error("Script kind {0} can't be represented in electrum")]
+    ElectrumUnsupportedScriptKind(String),
+    #[error("Taproot conversion is only supported for single signature wallets")]
+    TaprootMultisig,
+    #[error("Invalid multisig setup: {0}")]
+    InvalidMultisigSetup(String),
+    #[error("Invalid wallet policy: {0}")]
+    InvalidWalletPolicy(String),
+    #[error("Invalid generic JSON export: {0}")]
+    InvalidGenericJson(String),
+    #[error("Invalid Sparrow wallet: {0}")]
+    InvalidSparrowWallet(String),
+    #[error("Invalid BSMS record: {0}")]
+    InvalidBsmsRecord(String),
+    #[error("First address should be {0} but is {1}")]
+    FirstAddressMismatch(String, String),
+    #[error("Keystore {0} has no fingerprint and derivation")]
+    MissingKeyOrigin(usize),
+    #[error("Wallet is not a multisig wallet")]
+    NotMultisig,
+    #[error("Change descriptor {1} doesn't match external descriptor {0} on chain 1")]
+    ChangeDescriptorMismatch(String, String),
+    #[error("Hardened wildcards can't be represented in electrum: {0}")]
+    HardenedWildcard(String),
+    #[error("Invalid key origin: {0}")]
+    InvalidKeyOrigin(String),
+    #[error("Invalid character in descriptor: {0}")]
+    InvalidDescriptorCharacter(char),
+    #[error("Script kind {0} needs a multisig descriptor, use sortedmulti_descriptors")]
+    MultisigKindKey(String),
+    #[error("Script kind {0} can't be used in a multisig descriptor")]
+    SinglesigKindKey(String),
+    #[error("Keys of different script kinds: {0} and {1}")]
+    MixedScriptKinds(String, String),
+    #[error("The threshold of a multisig must be at least one")]
+    ZeroThreshold,
+    #[error("Keys of different networks")]
+    MixedNetworks,
+    #[error("Keystores {0} and {1} have the same xpub")]
+    DuplicateCosigner(usize, usize),
+    #[error("The xprv of keystore {0} doesn't belong to its xpub")]
+    KeystoreKeyMismatch(usize),
+    #[error("Keystore {0} has no xprv")]
+    MissingPrivateKey(usize),
+    #[error("Electrum server error: {0}")]
+    ElectrumServer(String),
+    #[error("Wallet name {0} can't be used as file name")]
+    InvalidWalletName(String),
+    #[error("Unknown key policy: {0}; expected public, private or require-private")]
+    UnknownKeyPolicy(String),
     #[error("Incorrect length of string representation: {0}")]
     InvalidLength(usize),
+    #[error("No script kind set and none of the keys has one, use a SLIP-132 key or set the kind")]
+    MissingScriptKind,
+    #[error("None of the script kinds generates all the given addresses")]
+    ScriptKindNotDetected,
     #[error("Unknown sentinel")]
     InvalidExtendedKeyVersion([u8; 4]),
     #[error("{0}")]
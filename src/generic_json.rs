@@ -0,0 +1,450 @@
+//! Watch-only exports of single signature keys in the generic JSON formats accepted by many mobile wallets:
+//! the flat `{"xpub","xfp","path","account"}` skeleton and Coldcard's generic JSON with `bip44`, `bip49` and `bip84` sections.
+use crate::electrum_wallet_file::format_path;
+use crate::{Electrum2DescriptorError, ElectrumExtendedKey, ElectrumExtendedPubKey};
+use bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint, Xpub};
+use bitcoin::NetworkKind;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// BIP purpose, Coldcard section and Coldcard name of the single signature script kinds
+const PURPOSES: [(u32, &str, &str, &str); 3] = [
+    (44, "pkh", "bip44", "p2pkh"),
+    (49, "sh(wpkh", "bip49", "p2sh-p2wpkh"),
+    (84, "wpkh", "bip84", "p2wpkh"),
+];
+
+/// The flat generic JSON skeleton of a single account
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct GenericJson {
+    /// BIP32 xpub, or a SLIP-132 one when parsing
+    pub xpub: String,
+    /// Fingerprint of the master key, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xfp: Option<String>,
+    /// Derivation path from the master key, its purpose determines the script kind
+    pub path: String,
+    pub account: u32,
+}
+
+/// Coldcard's generic JSON export with one section per script kind
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ColdcardGenericJson {
+    /// "BTC" for mainnet, "XTN" for testnet
+    pub chain: String,
+    pub xfp: String,
+    pub account: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bip44: Option<ColdcardAccount>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bip49: Option<ColdcardAccount>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bip84: Option<ColdcardAccount>,
+}
+
+/// One section of [`ColdcardGenericJson`]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ColdcardAccount {
+    pub name: String,
+    pub deriv: String,
+    /// BIP32 xpub
+    pub xpub: String,
+    /// SLIP-132 xpub
+    #[serde(rename = "_pub", default, skip_serializing_if = "Option::is_none")]
+    pub slip132: Option<String>,
+    /// First receiving address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first: Option<String>,
+}
+
+impl ElectrumExtendedPubKey {
+    /// Convert into the flat generic JSON skeleton.
+    /// Without origin the path is the standard one of the script kind, with the account taken from the xpub.
+    /// The purpose of an explicit origin path must match the script kind, as importers derive the kind from it.
+    pub fn to_generic_json(
+        &self,
+        origin: Option<(Fingerprint, DerivationPath)>,
+    ) -> Result<GenericJson, Electrum2DescriptorError> {
+        let purpose = purpose(self.kind())?;
+        let (xfp, path) = match origin {
+            Some((fingerprint, path)) => {
+                check_purpose(self.kind(), &path)?;
+                (Some(fingerprint.to_string().to_uppercase()), path)
+            }
+            None => (None, self.standard_path(purpose)?),
+        };
+        Ok(GenericJson {
+            xpub: self.xpub().to_string(),
+            xfp,
+            account: account(&path),
+            path: format_path(&path),
+        })
+    }
+
+    /// Parse the flat generic JSON skeleton into a SLIP-132 key and its origin, if the fingerprint is known.
+    /// A SLIP-132 xpub has to be of the script kind of the path purpose.
+    pub fn from_generic_json(
+        json: &GenericJson,
+    ) -> Result<(Self, Option<(Fingerprint, DerivationPath)>), Electrum2DescriptorError> {
+        let path = DerivationPath::from_str(&json.path)?;
+        let kind = kind(&path)?;
+        let xpub = match Xpub::from_str(&json.xpub) {
+            Ok(xpub) => xpub,
+            Err(_) => {
+                let slip132 = ElectrumExtendedPubKey::from_str(&json.xpub)?;
+                check_purpose(slip132.kind(), &path)?;
+                *slip132.xpub()
+            }
+        };
+        let origin = json
+            .xfp
+            .as_ref()
+            .map(|xfp| {
+                Fingerprint::from_str(xfp)
+                    .map(|fingerprint| (fingerprint, path.clone()))
+                    .map_err(|_| Electrum2DescriptorError::InvalidKeyOrigin(xfp.clone()))
+            })
+            .transpose()?;
+        Ok((ElectrumExtendedPubKey::new(xpub, kind.to_string()), origin))
+    }
+
+    /// The BIP44/49/84 path of this key, assuming it is an account key
+    fn standard_path(&self, purpose: u32) -> Result<DerivationPath, Electrum2DescriptorError> {
+        let coin = match self.xpub().network {
+            NetworkKind::Main => 0,
+            NetworkKind::Test => 1,
+        };
+        let account = match self.xpub().child_number {
+            ChildNumber::Hardened { index } if self.xpub().depth == 3 => index,
+            _ => 0,
+        };
+        Ok(DerivationPath::from(vec![
+            ChildNumber::from_hardened_idx(purpose)?,
+            ChildNumber::from_hardened_idx(coin)?,
+            ChildNumber::from_hardened_idx(account)?,
+        ]))
+    }
+}
+
+impl ColdcardGenericJson {
+    /// Combine the accounts of one master key, at most one per script kind, into a Coldcard generic JSON export
+    pub fn new(
+        fingerprint: Fingerprint,
+        keys: &[(ElectrumExtendedPubKey, DerivationPath)],
+    ) -> Result<Self, Electrum2DescriptorError> {
+        let network = keys
+            .first()
+            .map(|(key, _)| key.xpub().network)
+            .ok_or_else(|| Electrum2DescriptorError::InvalidGenericJson("no keys".to_string()))?;
+        let mut json = ColdcardGenericJson {
+            chain: match network {
+                NetworkKind::Main => "BTC",
+                NetworkKind::Test => "XTN",
+            }
+            .to_string(),
+            xfp: fingerprint.to_string().to_uppercase(),
+            account: account(&keys[0].1),
+            ..Default::default()
+        };
+        for (key, path) in keys {
+            check_purpose(key.kind(), path)?;
+            let (_, _, section, name) = PURPOSES
+                .iter()
+                .find(|(_, kind, _, _)| *kind == key.kind())
+                .ok_or_else(|| {
+                    Electrum2DescriptorError::UnknownScriptKind(key.kind().to_string())
+                })?;
+            let account = ColdcardAccount {
+                name: name.to_string(),
+                deriv: format_path(path),
+                xpub: key.xpub().to_string(),
+                slip132: Some(key.electrum_xpub()?),
+                first: Some(key.address(0, 0)?.to_string()),
+            };
+            let slot = json.section_mut(section);
+            if slot.is_some() || key.xpub().network != network {
+                return Err(Electrum2DescriptorError::InvalidGenericJson(format!(
+                    "{} key {}",
+                    section,
+                    key.xpub()
+                )));
+            }
+            *slot = Some(account);
+        }
+        Ok(json)
+    }
+
+    /// Parse the sections into SLIP-132 keys with their derivation from the master key.
+    /// The keys have to be of the network of `chain` and match the derivation and SLIP-132 xpub of their section.
+    pub fn keys(
+        &self,
+    ) -> Result<Vec<(ElectrumExtendedPubKey, DerivationPath)>, Electrum2DescriptorError> {
+        let network = match self.chain.as_str() {
+            "BTC" => NetworkKind::Main,
+            "XTN" | "XRT" => NetworkKind::Test,
+            chain => {
+                return Err(Electrum2DescriptorError::InvalidGenericJson(format!(
+                    "unknown chain {}",
+                    chain
+                )))
+            }
+        };
+        PURPOSES
+            .iter()
+            .filter_map(|(_, kind, section, _)| self.section(section).map(|acc| (kind, acc)))
+            .map(|(kind, account)| {
+                let key =
+                    ElectrumExtendedPubKey::new(Xpub::from_str(&account.xpub)?, kind.to_string());
+                let path = DerivationPath::from_str(&account.deriv)?;
+                check_purpose(kind, &path)?;
+                if key.xpub().network != network {
+                    return Err(Electrum2DescriptorError::InvalidGenericJson(format!(
+                        "key {} isn't a {} key",
+                        account.xpub, self.chain
+                    )));
+                }
+                if let Some(slip132) = &account.slip132 {
+                    let parsed = ElectrumExtendedPubKey::from_str(slip132)?;
+                    if parsed.xpub() != key.xpub() || parsed.kind() != key.kind() {
+                        return Err(Electrum2DescriptorError::InvalidGenericJson(format!(
+                            "{} doesn't match {} of kind {}",
+                            slip132, account.xpub, kind
+                        )));
+                    }
+                }
+                Ok((key, path))
+            })
+            .collect()
+    }
+
+    /// The fingerprint of the master key
+    pub fn fingerprint(&self) -> Result<Fingerprint, Electrum2DescriptorError> {
+        Fingerprint::from_str(&self.xfp)
+            .map_err(|_| Electrum2DescriptorError::InvalidKeyOrigin(self.xfp.clone()))
+    }
+
+    fn section(&self, section: &str) -> Option<&ColdcardAccount> {
+        match section {
+            "bip44" => self.bip44.as_ref(),
+            "bip49" => self.bip49.as_ref(),
+            _ => self.bip84.as_ref(),
+        }
+    }
+
+    fn section_mut(&mut self, section: &str) -> &mut Option<ColdcardAccount> {
+        match section {
+            "bip44" => &mut self.bip44,
+            "bip49" => &mut self.bip49,
+            _ => &mut self.bip84,
+        }
+    }
+}
+
+/// The BIP purpose of a single signature script kind
+fn purpose(kind: &str) -> Result<u32, Electrum2DescriptorError> {
+    PURPOSES
+        .iter()
+        .find(|(_, k, _, _)| *k == kind)
+        .map(|(purpose, _, _, _)| *purpose)
+        .ok_or_else(|| Electrum2DescriptorError::UnknownScriptKind(kind.to_string()))
+}
+
+/// The script kind implied by the purpose of a BIP44/49/84 path
+fn kind(path: &DerivationPath) -> Result<&'static str, Electrum2DescriptorError> {
+    let purpose = match path.into_iter().next() {
+        Some(ChildNumber::Hardened { index }) => *index,
+        _ => {
+            return Err(Electrum2DescriptorError::InvalidGenericJson(
+                path.to_string(),
+            ))
+        }
+    };
+    PURPOSES
+        .iter()
+        .find(|(p, _, _, _)| *p == purpose)
+        .map(|(_, kind, _, _)| *kind)
+        .ok_or_else(|| Electrum2DescriptorError::InvalidGenericJson(path.to_string()))
+}
+
+/// Fails unless the purpose of the path is the one of the script kind
+fn check_purpose(kind_of_key: &str, path: &DerivationPath) -> Result<(), Electrum2DescriptorError> {
+    if kind(path)? != kind_of_key {
+        return Err(Electrum2DescriptorError::InvalidGenericJson(format!(
+            "path {} doesn't match script kind {}",
+            path, kind_of_key
+        )));
+    }
+    Ok(())
+}
+
+/// The account number of a BIP44/49/84 path
+fn account(path: &DerivationPath) -> u32 {
+    match path.into_iter().nth(2) {
+        Some(ChildNumber::Hardened { index }) => *index,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generic_json() {
+        let expub = ElectrumExtendedPubKey::from_str("vpub5VXaSncXqxLbdmvrC4Y8z9CszPwuEscADoetWhfrxDFzPUbL5nbVtanYDkrVEutkv9n5A5aCcvRC9swbjDKgHjCZ2tAeae8VsBuPbS8KpXv").unwrap();
+        let json = expub.to_generic_json(None).unwrap();
+        assert_eq!(json.path, "m/84'/1'/0'");
+        assert_eq!(json.account, 0);
+        assert_eq!(json.xfp, None);
+        let (parsed, origin) = ElectrumExtendedPubKey::from_generic_json(&json).unwrap();
+        assert_eq!(
+            parsed.electrum_xpub().unwrap(),
+            expub.electrum_xpub().unwrap()
+        );
+        assert_eq!(origin, None);
+
+        let origin = (
+            Fingerprint::from_str("0f056943").unwrap(),
+            DerivationPath::from_str("m/84'/1'/5'").unwrap(),
+        );
+        let json = expub.to_generic_json(Some(origin.clone())).unwrap();
+        assert_eq!(json.xfp.as_deref(), Some("0F056943"));
+        assert_eq!(json.account, 5);
+        let (parsed, parsed_origin) = ElectrumExtendedPubKey::from_generic_json(&json).unwrap();
+        assert_eq!(
+            parsed.electrum_xpub().unwrap(),
+            expub.electrum_xpub().unwrap()
+        );
+        assert_eq!(parsed_origin, Some(origin));
+
+        let wrong_purpose = (
+            Fingerprint::from_str("0f056943").unwrap(),
+            DerivationPath::from_str("m/44'/1'/0'").unwrap(),
+        );
+        assert!(matches!(
+            expub.to_generic_json(Some(wrong_purpose)),
+            Err(Electrum2DescriptorError::InvalidGenericJson(_))
+        ));
+        let master = (
+            Fingerprint::from_str("0f056943").unwrap(),
+            DerivationPath::master(),
+        );
+        assert!(matches!(
+            expub.to_generic_json(Some(master)),
+            Err(Electrum2DescriptorError::InvalidGenericJson(_))
+        ));
+
+        let slip132_kind = GenericJson {
+            xpub: expub.electrum_xpub().unwrap(),
+            path: "m/44'/1'/0'".to_string(),
+            ..json.clone()
+        };
+        assert!(matches!(
+            ElectrumExtendedPubKey::from_generic_json(&slip132_kind),
+            Err(Electrum2DescriptorError::InvalidGenericJson(_))
+        ));
+
+        let unknown = GenericJson {
+            path: "m/48'/1'/0'/2'".to_string(),
+            ..json
+        };
+        assert!(matches!(
+            ElectrumExtendedPubKey::from_generic_json(&unknown),
+            Err(Electrum2DescriptorError::InvalidGenericJson(_))
+        ));
+    }
+
+    #[test]
+    fn test_coldcard_generic_json() {
+        let json = r#"{
+            "chain": "XTN",
+            "xfp": "02B4EBE0",
+            "account": 0,
+            "xpub": "tpubD6NzVbkrYhZ4WZf8MyhpHbPZn4rZmcSmpv69Sqisni3aVxcA49JTHFBmjFSknmHLbfYJWAhCTkFU7m2jAuvssSxMgMXeQzcsHb1nWVsuLPj",
+            "bip44": {
+                "name": "p2pkh",
+                "deriv": "m/44'/1'/0'",
+                "xpub": "tpubDDMEUEUhjHMkL4YwXybyaDspnAPdyPis2mGZ25PtNomKYZaX5vueKdo2aBsLaWxPxr2RECbTiHLuuFbqQNvD9pmv2YXupYre6vDfMogweUH",
+                "desc": "pkh([02b4ebe0/44h/1h/0h]tpubDDMEUEUhjHMkL4YwXybyaDspnAPdyPis2mGZ25PtNomKYZaX5vueKdo2aBsLaWxPxr2RECbTiHLuuFbqQNvD9pmv2YXupYre6vDfMogweUH/<0;1>/*)#ncc98pq5",
+                "first": "mj5wWN1HESxzHyR9kE69iv3d6EmddsJ6Nj"
+            },
+            "bip84": {
+                "name": "p2wpkh",
+                "deriv": "m/84'/1'/0'",
+                "xpub": "tpubDCixJUQk4AmiUgUcRaWgGwT1mwzSwHid4s8B1vTGAStVemnJuph7ES15WVYsgVUj6YwdoaUXjTCkrQkNo44p7YjS32wGrLS6rEEyuDVxN97",
+                "_pub": "vpub5YgoAuFE3XjJWHuXJfxLeqve6tbu4fEZh1VS6acM4XNqoHV9LHWtqs1yE3YvABDeVNB7h3mYH2fcPf2dxoPYdduwEko7zz8tipXHN7tkT7m",
+                "desc": "wpkh([02b4ebe0/84h/1h/0h]tpubDCixJUQk4AmiUgUcRaWgGwT1mwzSwHid4s8B1vTGAStVemnJuph7ES15WVYsgVUj6YwdoaUXjTCkrQkNo44p7YjS32wGrLS6rEEyuDVxN97/<0;1>/*)#8auad8ed",
+                "first": "tb1qhne8k2s09fx47tvl5809znzjqcavkesdqhavst"
+            },
+            "bip48_2": {
+                "name": "p2wsh",
+                "deriv": "m/48'/1'/0'/2'",
+                "xpub": "tpubDEH8K4YdCMRw3inCUtvNGEBPbmiqHuH9hurSmD7T7rLfbNNBSgdWWLZHgZMw6KMfeKKXXvDApBtX555qYLL4zWy1S6U3cCCMzittei9TNgV"
+            }
+        }"#;
+        let parsed_keys = |json| {
+            serde_json::from_str::<ColdcardGenericJson>(json)
+                .unwrap()
+                .keys()
+                .unwrap()
+        };
+        let parsed: ColdcardGenericJson = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            parsed.fingerprint().unwrap(),
+            Fingerprint::from_str("02b4ebe0").unwrap()
+        );
+        let keys = parsed.keys().unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].0.kind(), "pkh");
+        assert_eq!(keys[0].0.xpub().depth, 3);
+        assert_eq!(keys[1].0.electrum_xpub().unwrap(), "vpub5YgoAuFE3XjJWHuXJfxLeqve6tbu4fEZh1VS6acM4XNqoHV9LHWtqs1yE3YvABDeVNB7h3mYH2fcPf2dxoPYdduwEko7zz8tipXHN7tkT7m");
+        assert_eq!(keys[1].1, DerivationPath::from_str("m/84'/1'/0'").unwrap());
+
+        let exported = ColdcardGenericJson::new(parsed.fingerprint().unwrap(), &keys).unwrap();
+        assert_eq!(exported.chain, "XTN");
+        assert_eq!(exported.xfp, "02B4EBE0");
+        assert_eq!(
+            exported.bip44.as_ref().unwrap().first,
+            parsed.bip44.clone().unwrap().first
+        );
+        assert_eq!(
+            exported.bip84.as_ref().unwrap().first,
+            parsed.bip84.clone().unwrap().first
+        );
+        assert_eq!(
+            exported.bip84.as_ref().unwrap().slip132,
+            parsed.bip84.clone().unwrap().slip132
+        );
+        assert_eq!(exported.bip49, None);
+        assert_eq!(exported.keys().unwrap().len(), 2);
+
+        let mut duplicate = parsed_keys(json);
+        duplicate.push(parsed_keys(json).remove(1));
+        assert!(matches!(
+            ColdcardGenericJson::new(exported.fingerprint().unwrap(), &duplicate),
+            Err(Electrum2DescriptorError::InvalidGenericJson(_))
+        ));
+
+        let mainnet = ColdcardGenericJson {
+            chain: "BTC".to_string(),
+            ..parsed.clone()
+        };
+        assert!(matches!(
+            mainnet.keys(),
+            Err(Electrum2DescriptorError::InvalidGenericJson(_))
+        ));
+        // the SLIP-132 xpub of the bip84 section encoded for sh(wpkh)
+        let mut wrong_kind = parsed.clone();
+        wrong_kind.bip84.as_mut().unwrap().slip132 = Some("upub5DrXsEaJtrBpeziQUKAiSkq8vvTT83F4mtyDKBiTgWzxkBfv5dMLDoMqCqbLAGZj5j4JwaAypNK4WNR5F6yXqQELNR6hR5KQT6TdyUFbiSd".to_string());
+        assert!(matches!(
+            wrong_kind.keys(),
+            Err(Electrum2DescriptorError::InvalidGenericJson(_))
+        ));
+        let mut wrong_path = parsed;
+        wrong_path.bip84.as_mut().unwrap().deriv = "m/44'/1'/0'".to_string();
+        assert!(matches!(
+            wrong_path.keys(),
+            Err(Electrum2DescriptorError::InvalidGenericJson(_))
+        ));
+    }
+}
@@ -1,18 +1,44 @@
+pub mod address;
+#[cfg(feature = "bdk")]
+mod bdk;
+#[cfg(feature = "wallet_file")]
+pub mod bitcoin_core;
+pub mod checksum;
 pub mod electrum_extended_priv_key;
 pub mod electrum_extended_pub_key;
+#[cfg(feature = "electrum_server")]
+pub mod electrum_server;
 #[cfg(feature = "wallet_file")]
 pub mod electrum_wallet_file;
 pub mod errors;
+#[cfg(feature = "wallet_file")]
+pub mod generic_json;
+#[cfg(feature = "wallet_file")]
+pub mod multisig_builder;
+#[cfg(feature = "wallet_file")]
+mod multisig_setup;
+#[cfg(feature = "wallet_file")]
+mod sparrow;
+#[cfg(feature = "wallet_file")]
+pub mod wallet_policy;
 
+pub use address::{electrum_scripthash, find_addresses, AddressMatch, AddressSource, ScriptHash};
+pub use checksum::descriptor_checksum;
 pub use electrum_extended_priv_key::ElectrumExtendedPrivKey;
 pub use electrum_extended_pub_key::ElectrumExtendedPubKey;
 #[cfg(feature = "wallet_file")]
 pub use electrum_wallet_file::ElectrumWalletFile;
 pub use errors::Electrum2DescriptorError;
+#[cfg(feature = "wallet_file")]
+pub use multisig_builder::MultisigBuilder;
+use std::str::FromStr;
+#[cfg(feature = "wallet_file")]
+pub use wallet_policy::WalletPolicy;
 
 pub trait ElectrumExtendedKey {
-    /// Returns internal and external descriptor
-    fn to_descriptors(&self) -> Descriptors;
+    /// Returns internal and external descriptor.
+    /// Fails for the multisig kinds of SLIP-132 keys (Ypub, Zpub, Upub, Vpub), which need [`ElectrumExtendedPubKey::sortedmulti_descriptors`].
+    fn to_descriptors(&self) -> Result<Descriptors, Electrum2DescriptorError>;
 
     /// Returns the bitcoin extended key (xpub or xprv) as String
     fn xkey_str(&self) -> String;
@@ -27,3 +53,65 @@ pub struct Descriptors {
     pub external: String,
     pub change: String,
 }
+
+impl Descriptors {
+    /// Builds `kind(key/0/*)` and `kind(key/1/*)` for a single signature script kind
+    pub(crate) fn singlesig(kind: &str, key: &str) -> Result<Self, Electrum2DescriptorError> {
+        if kind == "sh(wsh" || kind == "wsh" {
+            return Err(Electrum2DescriptorError::MultisigKindKey(kind.to_string()));
+        }
+        Ok(Descriptors::from_external(format!("{}({}/0/*", kind, key)))
+    }
+
+    /// Builds `sortedmulti` descriptors from keys of the given kind, where "pkh" stands for legacy p2sh multisig
+    pub(crate) fn sortedmulti(kind: &str, threshold: u8, keys: &[String]) -> Self {
+        let prefix = match kind {
+            "pkh" => "sh",
+            kind => kind,
+        };
+        let keys = keys
+            .iter()
+            .map(|key| format!("{}/0/*", key))
+            .collect::<Vec<_>>();
+        Descriptors::from_external(format!(
+            "{}(sortedmulti({},{})",
+            prefix,
+            threshold,
+            keys.join(",")
+        ))
+    }
+
+    /// Closes the open parentheses of the external descriptor and derives the change descriptor from it
+    fn from_external(mut external: String) -> Self {
+        let opening = external.matches('(').count();
+        let closing = external.matches(')').count();
+        external += &")".repeat(opening - closing);
+        let change = external.replace("/0/*", "/1/*");
+        Descriptors { external, change }
+    }
+}
+
+/// Which keys go into generated descriptors, so watch-only exports don't leak xprvs by accident
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyPolicy {
+    /// Only xpubs, even where the xprv is known
+    PublicOnly,
+    /// The xprv where known, else the xpub
+    PrivateWhereAvailable,
+    /// Only xprvs, fails if any xprv is missing
+    RequirePrivate,
+}
+
+impl FromStr for KeyPolicy {
+    type Err = Electrum2DescriptorError;
+
+    /// Parses "public", "private" or "require-private"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "public" => Ok(KeyPolicy::PublicOnly),
+            "private" => Ok(KeyPolicy::PrivateWhereAvailable),
+            "require-private" => Ok(KeyPolicy::RequirePrivate),
+            _ => Err(Electrum2DescriptorError::UnknownKeyPolicy(s.to_string())),
+        }
+    }
+}
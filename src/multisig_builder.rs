@@ -0,0 +1,148 @@
+//! Builder for multisig wallets from cosigner keys in the various formats electrum and descriptors use
+use crate::electrum_wallet_file::{account_key, KeyOrigin, Keystore};
+use crate::{
+    Descriptors, Electrum2DescriptorError, ElectrumExtendedKey, ElectrumExtendedPrivKey,
+    ElectrumExtendedPubKey, ElectrumWalletFile, KeyPolicy,
+};
+use bitcoin::bip32::{Xpriv, Xpub};
+use bitcoin::NetworkKind;
+use std::str::FromStr;
+
+/// A cosigner key as added to the builder
+struct Cosigner {
+    /// Script kind of SLIP-132 keys, BIP32 keys take the one of the builder
+    kind: Option<String>,
+    /// BIP32 xpub or xprv
+    xkey: String,
+    network: NetworkKind,
+    origin: Option<KeyOrigin>,
+}
+
+/// Builds a `sortedmulti` wallet from cosigner keys.
+/// ```
+/// # use libelectrum2descriptors::MultisigBuilder;
+/// let wallet = MultisigBuilder::new(2)
+///     .slip132("Vpub5mUs4UNPA6T3VAmcTWTJ2nCV2oAEFQqBNQQDH62NQNpdAMSyL2Nd3vZXF6uQfNeiCst7asUapZWM9AKmsYCK1BjUrEVhiVm9M4qnbHvDRDe", None)?
+///     .bip32("[6bfac2d6/48'/100'/0'/2']tpubDEbkvhmJoZMq3SUNqEf3aEsubvqsCUPc7rroHkGERgS7qA1gQVMxUPrgzth6x43odirLohwf4aMHpvcnWi3jCB2xkizv8T4B2KqLRZVLC6K")?
+///     .build()?;
+/// # Ok::<(), libelectrum2descriptors::Electrum2DescriptorError>(())
+/// ```
+pub struct MultisigBuilder {
+    threshold: u8,
+    kind: Option<String>,
+    cosigners: Vec<Cosigner>,
+}
+
+impl MultisigBuilder {
+    /// Start a multisig wallet requiring `threshold` signatures
+    pub fn new(threshold: u8) -> Self {
+        MultisigBuilder {
+            threshold,
+            kind: None,
+            cosigners: Vec::new(),
+        }
+    }
+
+    /// Set the script kind: "pkh" for legacy p2sh, "sh(wsh" or "wsh".
+    /// Required if only BIP32 keys are added, else it must match the kind of the SLIP-132 keys.
+    pub fn kind(mut self, kind: &str) -> Self {
+        self.kind = Some(kind.to_string());
+        self
+    }
+
+    /// Add a SLIP-132 xpub or xprv like Zpub or Vprv, optionally with the origin of the key
+    pub fn slip132(
+        self,
+        key: &str,
+        origin: Option<KeyOrigin>,
+    ) -> Result<Self, Electrum2DescriptorError> {
+        match ElectrumExtendedPrivKey::from_str(key) {
+            Ok(exprv) => Ok(self.priv_key(&exprv, origin)),
+            Err(_) => Ok(self.pub_key(&ElectrumExtendedPubKey::from_str(key)?, origin)),
+        }
+    }
+
+    /// Add an electrum extended public key, optionally with its origin
+    pub fn pub_key(self, key: &ElectrumExtendedPubKey, origin: Option<KeyOrigin>) -> Self {
+        self.push(Some(key.kind()), key.xkey_str(), key.xpub().network, origin)
+    }
+
+    /// Add an electrum extended private key, optionally with its origin
+    pub fn priv_key(self, key: &ElectrumExtendedPrivKey, origin: Option<KeyOrigin>) -> Self {
+        self.push(Some(key.kind()), key.xkey_str(), key.xprv().network, origin)
+    }
+
+    /// Add a BIP32 xpub or xprv as descriptor key, with optional origin and derivation steps: `[fingerprint/derivation]xpub/steps`
+    pub fn bip32(self, key: &str) -> Result<Self, Electrum2DescriptorError> {
+        let (origin, xkey) = account_key(key)?;
+        let network = match Xpriv::from_str(&xkey) {
+            Ok(xprv) => xprv.network,
+            Err(_) => Xpub::from_str(&xkey)?.network,
+        };
+        Ok(self.push(None, xkey, network, origin))
+    }
+
+    fn push(
+        mut self,
+        kind: Option<&str>,
+        xkey: String,
+        network: NetworkKind,
+        origin: Option<KeyOrigin>,
+    ) -> Self {
+        self.cosigners.push(Cosigner {
+            kind: kind.map(str::to_string),
+            xkey,
+            network,
+            origin,
+        });
+        self
+    }
+
+    /// Validate the cosigners and build the electrum wallet
+    pub fn build(self) -> Result<ElectrumWalletFile, Electrum2DescriptorError> {
+        if self.cosigners.len() < 2 {
+            return Err(Electrum2DescriptorError::MultisigFewSigners);
+        }
+        if self.threshold == 0 {
+            return Err(Electrum2DescriptorError::ZeroThreshold);
+        }
+        let kind = self
+            .kind
+            .clone()
+            .or_else(|| self.cosigners.iter().find_map(|c| c.kind.clone()))
+            .ok_or(Electrum2DescriptorError::MissingScriptKind)?;
+        if !["pkh", "sh(wsh", "wsh"].contains(&kind.as_str()) {
+            return Err(Electrum2DescriptorError::SinglesigKindKey(kind));
+        }
+        let network = self.cosigners[0].network;
+        let keystores = self
+            .cosigners
+            .iter()
+            .map(|cosigner| {
+                if let Some(other) = cosigner.kind.as_ref().filter(|k| **k != kind) {
+                    return Err(Electrum2DescriptorError::MixedScriptKinds(
+                        kind.clone(),
+                        other.clone(),
+                    ));
+                }
+                if cosigner.network != network {
+                    return Err(Electrum2DescriptorError::MixedNetworks);
+                }
+                let keystore = Keystore::new(&kind, &cosigner.xkey)?;
+                Ok(match &cosigner.origin {
+                    Some((fingerprint, path)) => keystore.with_origin(*fingerprint, path),
+                    None => keystore,
+                })
+            })
+            .collect::<Result<Vec<_>, Electrum2DescriptorError>>()?;
+        ElectrumWalletFile::new(&keystores, self.threshold)
+    }
+
+    /// Validate the cosigners and build the descriptors with key origins and the keys selected by `policy`
+    pub fn build_descriptors(
+        self,
+        policy: KeyPolicy,
+    ) -> Result<Descriptors, Electrum2DescriptorError> {
+        self.build()?.to_descriptors_with_policy(policy, true)
+    }
+}
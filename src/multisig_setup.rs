@@ -0,0 +1,232 @@
+//! Import and export of multisig setup files as used by Coldcard, Sparrow, Specter and BIP-129 coordinators
+use crate::electrum_wallet_file::{format_path, Keystore, WalletType};
+use crate::{address, AddressSource};
+use crate::{
+    Electrum2DescriptorError, ElectrumExtendedKey, ElectrumExtendedPubKey, ElectrumWalletFile,
+    KeyPolicy,
+};
+use bitcoin::bip32::{DerivationPath, Fingerprint, Xpub};
+use bitcoin::Address;
+use regex::Regex;
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// The parts of a Specter wallet export we need. Sparrow exports the same format for Specter.
+#[derive(Deserialize)]
+struct SpecterWallet {
+    descriptor: String,
+}
+
+impl ElectrumWalletFile {
+    /// Construct from a Coldcard multisig setup file, as exported by Coldcard and Sparrow
+    /// ```text
+    /// Name: MyWallet
+    /// Policy: 2 of 3
+    /// Derivation: m/48'/1'/0'/2'
+    /// Format: P2WSH
+    ///
+    /// 0F056943: tpub...
+    /// ```
+    /// A `Derivation:` line applies to the keys following it. A missing `Format:` means P2SH, as in Coldcard.
+    pub fn from_coldcard_multisig(setup: &str) -> Result<Self, Electrum2DescriptorError> {
+        let policy_re = Regex::new(r#"^(\d+)\s*of\s*(\d+)$"#)?;
+        let mut policy = None;
+        let mut kind = "pkh";
+        let mut derivation = None;
+        let mut keys = Vec::new();
+
+        for line in setup.lines().map(str::trim) {
+            let line = line
+                .strip_prefix("# derivation:")
+                .map(|d| format!("Derivation:{}", d))
+                .unwrap_or_else(|| line.to_string());
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once(':')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| Electrum2DescriptorError::InvalidMultisigSetup(line.clone()))?;
+            match key.to_lowercase().as_str() {
+                "name" => {}
+                "policy" => {
+                    let captures = policy_re.captures(value).ok_or_else(|| {
+                        Electrum2DescriptorError::InvalidMultisigSetup(line.clone())
+                    })?;
+                    let x: u8 = captures[1].parse().map_err(|_| {
+                        Electrum2DescriptorError::InvalidMultisigSetup(line.clone())
+                    })?;
+                    let y: usize = captures[2].parse().map_err(|_| {
+                        Electrum2DescriptorError::InvalidMultisigSetup(line.clone())
+                    })?;
+                    policy = Some((x, y));
+                }
+                "format" => {
+                    kind = match value.to_uppercase().as_str() {
+                        "P2SH" => "pkh",
+                        "P2SH-P2WSH" | "P2WSH-P2SH" => "sh(wsh",
+                        "P2WSH" => "wsh",
+                        _ => {
+                            return Err(Electrum2DescriptorError::UnknownScriptKind(
+                                value.to_string(),
+                            ))
+                        }
+                    }
+                }
+                "derivation" => derivation = Some(DerivationPath::from_str(value)?),
+                fingerprint => {
+                    let fingerprint = Fingerprint::from_str(fingerprint).map_err(|_| {
+                        Electrum2DescriptorError::InvalidMultisigSetup(line.clone())
+                    })?;
+                    let path = derivation.clone().ok_or_else(|| {
+                        Electrum2DescriptorError::InvalidMultisigSetup(format!(
+                            "missing derivation for {}",
+                            fingerprint
+                        ))
+                    })?;
+                    keys.push((fingerprint, path, parse_xpub(value)?));
+                }
+            }
+        }
+
+        let (x, y) = policy.ok_or_else(|| {
+            Electrum2DescriptorError::InvalidMultisigSetup("missing policy".to_string())
+        })?;
+        if keys.len() != y {
+            return Err(Electrum2DescriptorError::WrongNumberOfKeyStores(
+                keys.len(),
+                y,
+            ));
+        }
+        let keystores = keys
+            .iter()
+            .map(|(fingerprint, path, xpub)| {
+                Ok(Keystore::new(kind, &xpub.to_string())?.with_origin(*fingerprint, path))
+            })
+            .collect::<Result<Vec<_>, Electrum2DescriptorError>>()?;
+        ElectrumWalletFile::new(&keystores, x)
+    }
+
+    /// Export as Coldcard multisig setup file, to register the wallet on hardware signers.
+    /// Requires the fingerprint and derivation of every keystore.
+    pub fn to_coldcard_multisig(&self, name: &str) -> Result<String, Electrum2DescriptorError> {
+        let (x, y) = match self.wallet_type() {
+            WalletType::Multisig(x, y) => (x, y),
+            WalletType::Standard => return Err(Electrum2DescriptorError::NotMultisig),
+        };
+        let xkeys = self
+            .keystores()
+            .iter()
+            .map(|ks| ElectrumExtendedPubKey::from_str(&ks.xpub))
+            .collect::<Result<Vec<_>, _>>()?;
+        let format = match xkeys[0].kind() {
+            "pkh" => "P2SH",
+            "sh(wsh" => "P2SH-P2WSH",
+            "wsh" => "P2WSH",
+            kind => {
+                return Err(Electrum2DescriptorError::UnknownScriptKind(
+                    kind.to_string(),
+                ))
+            }
+        };
+
+        let mut setup = vec![
+            "# Coldcard Multisig setup file (exported by electrum2descriptors)".to_string(),
+            "#".to_string(),
+            format!("Name: {}", name),
+            format!("Policy: {} of {}", x, y),
+            format!("Format: {}", format),
+        ];
+        for (i, (keystore, xkey)) in self.keystores().iter().zip(&xkeys).enumerate() {
+            let (fingerprint, path) = keystore
+                .origin()?
+                .ok_or(Electrum2DescriptorError::MissingKeyOrigin(i))?;
+            setup.push(String::new());
+            setup.push(format!("Derivation: {}", format_path(&path)));
+            setup.push(format!(
+                "{}: {}",
+                fingerprint.to_string().to_uppercase(),
+                xkey.xpub()
+            ));
+        }
+        Ok(setup.join("\n") + "\n")
+    }
+
+    /// Construct from a BIP-129 (BSMS) 1.0 descriptor record and verify its first address.
+    /// ```text
+    /// BSMS 1.0
+    /// wsh(sortedmulti(2,[fingerprint/derivation]xpub/**,...))
+    /// /0/*,/1/*
+    /// bc1q...
+    /// ```
+    /// Electrum only supports `/0/*,/1/*` as path restrictions.
+    pub fn from_bsms(record: &str) -> Result<Self, Electrum2DescriptorError> {
+        let lines = record
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>();
+        let [version, template, restrictions, first_address] = lines[..] else {
+            return Err(Electrum2DescriptorError::InvalidBsmsRecord(
+                "expected four lines".to_string(),
+            ));
+        };
+        if version != "BSMS 1.0" {
+            return Err(Electrum2DescriptorError::InvalidBsmsRecord(
+                version.to_string(),
+            ));
+        }
+        if restrictions != "/0/*,/1/*" && restrictions != "No path restrictions" {
+            return Err(Electrum2DescriptorError::InvalidBsmsRecord(
+                restrictions.to_string(),
+            ));
+        }
+
+        let wallet = ElectrumWalletFile::from_descriptor(&template.replace("/**", "/0/*"))?;
+        let script = wallet.script_pubkey(0, 0)?;
+        let found =
+            address::require_network(Address::from_str(first_address)?, wallet.network_kind()?)?;
+        if found.script_pubkey() != script {
+            return Err(Electrum2DescriptorError::FirstAddressMismatch(
+                Address::from_script(&script, wallet.network()?)?.to_string(),
+                first_address.to_string(),
+            ));
+        }
+        Ok(wallet)
+    }
+
+    /// Export as BIP-129 (BSMS) 1.0 descriptor record. Requires the fingerprint and derivation of every keystore.
+    /// Only xpubs are exported, even if the wallet contains xprvs.
+    pub fn to_bsms(&self) -> Result<String, Electrum2DescriptorError> {
+        if *self.wallet_type() == WalletType::Standard {
+            return Err(Electrum2DescriptorError::NotMultisig);
+        }
+        for (i, keystore) in self.keystores().iter().enumerate() {
+            keystore
+                .origin()?
+                .ok_or(Electrum2DescriptorError::MissingKeyOrigin(i))?;
+        }
+        let descriptors = self.to_descriptors_with_policy(KeyPolicy::PublicOnly, true)?;
+        let first_address = Address::from_script(&self.script_pubkey(0, 0)?, self.network()?)?;
+        Ok(format!(
+            "BSMS 1.0\n{}\n/0/*,/1/*\n{}\n",
+            descriptors.external.replace("/0/*", "/**"),
+            first_address
+        ))
+    }
+
+    /// Construct from a Specter wallet export, which Sparrow can produce as well.
+    /// Only the descriptor with its key origins is used.
+    pub fn from_specter_json(json: &str) -> Result<Self, Electrum2DescriptorError> {
+        let wallet: SpecterWallet = serde_json::from_str(json)?;
+        ElectrumWalletFile::from_descriptor(&wallet.descriptor)
+    }
+}
+
+/// Parse a BIP32 xpub, or a SLIP-132 one as accepted by Coldcard
+pub(crate) fn parse_xpub(xpub: &str) -> Result<Xpub, Electrum2DescriptorError> {
+    match Xpub::from_str(xpub) {
+        Ok(xpub) => Ok(xpub),
+        Err(_) => Ok(*ElectrumExtendedPubKey::from_str(xpub)?.xpub()),
+    }
+}
@@ -0,0 +1,205 @@
+//! Import and export of the JSON wallet format of Sparrow for watch-only wallets
+use crate::electrum_wallet_file::{format_path, Keystore, WalletType};
+use crate::multisig_setup::parse_xpub;
+use crate::{
+    Electrum2DescriptorError, ElectrumExtendedKey, ElectrumExtendedPubKey, ElectrumWalletFile,
+};
+use bitcoin::bip32::{DerivationPath, Fingerprint};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SparrowWallet {
+    name: String,
+    policy_type: String,
+    script_type: String,
+    default_policy: SparrowPolicy,
+    keystores: Vec<SparrowKeystore>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct SparrowPolicy {
+    name: String,
+    miniscript: SparrowMiniscript,
+}
+
+#[derive(Deserialize, Serialize)]
+struct SparrowMiniscript {
+    script: String,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SparrowKeystore {
+    label: String,
+    #[serde(default)]
+    source: String,
+    #[serde(default)]
+    wallet_model: String,
+    key_derivation: SparrowKeyDerivation,
+    extended_public_key: String,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SparrowKeyDerivation {
+    master_fingerprint: String,
+    derivation_path: String,
+}
+
+/// Sparrow's script type names with the corresponding electrum script kind of single signature and multisig wallets
+const SCRIPT_TYPES: [(&str, &str, WalletKind); 6] = [
+    ("P2PKH", "pkh", WalletKind::Single),
+    ("P2SH_P2WPKH", "sh(wpkh", WalletKind::Single),
+    ("P2WPKH", "wpkh", WalletKind::Single),
+    ("P2SH", "pkh", WalletKind::Multi),
+    ("P2SH_P2WSH", "sh(wsh", WalletKind::Multi),
+    ("P2WSH", "wsh", WalletKind::Multi),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum WalletKind {
+    Single,
+    Multi,
+}
+
+impl ElectrumWalletFile {
+    /// Export as watch-only Sparrow wallet in its JSON format. Requires the fingerprint and derivation of every keystore.
+    /// Only xpubs are exported, even if the wallet contains xprvs.
+    pub fn to_sparrow_json(&self, name: &str) -> Result<String, Electrum2DescriptorError> {
+        let xkeys = self
+            .keystores()
+            .iter()
+            .map(|ks| ElectrumExtendedPubKey::from_str(&ks.xpub))
+            .collect::<Result<Vec<_>, _>>()?;
+        let wallet_kind = match self.wallet_type() {
+            WalletType::Standard => WalletKind::Single,
+            WalletType::Multisig(_x, _y) => WalletKind::Multi,
+        };
+        let script_type = SCRIPT_TYPES
+            .iter()
+            .find(|(_, kind, wk)| *kind == xkeys[0].kind() && *wk == wallet_kind)
+            .map(|(script_type, _, _)| script_type.to_string())
+            .ok_or_else(|| {
+                Electrum2DescriptorError::UnknownScriptKind(xkeys[0].kind().to_string())
+            })?;
+
+        let keystores = self
+            .keystores()
+            .iter()
+            .zip(&xkeys)
+            .enumerate()
+            .map(|(i, (keystore, xkey))| {
+                let (fingerprint, path) = keystore
+                    .origin()?
+                    .ok_or(Electrum2DescriptorError::MissingKeyOrigin(i))?;
+                Ok(SparrowKeystore {
+                    label: format!("Keystore {}", i + 1),
+                    source: "SW_WATCH".to_string(),
+                    wallet_model: "SPARROW".to_string(),
+                    key_derivation: SparrowKeyDerivation {
+                        master_fingerprint: fingerprint.to_string(),
+                        derivation_path: format_path(&path),
+                    },
+                    extended_public_key: xkey.xpub().to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>, Electrum2DescriptorError>>()?;
+        let labels = keystores
+            .iter()
+            .map(|ks| ks.label.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+        let default_policy = match self.wallet_type() {
+            WalletType::Standard => SparrowPolicy {
+                name: "Single Signature".to_string(),
+                miniscript: SparrowMiniscript {
+                    script: format!("{}({})", xkeys[0].kind(), labels)
+                        + &")".repeat(xkeys[0].kind().matches('(').count()),
+                },
+            },
+            WalletType::Multisig(x, _y) => SparrowPolicy {
+                name: "Multi Signature".to_string(),
+                miniscript: SparrowMiniscript {
+                    script: format!("sortedmulti({},{})", x, labels),
+                },
+            },
+        };
+
+        Ok(serde_json::to_string_pretty(&SparrowWallet {
+            name: name.to_string(),
+            policy_type: match wallet_kind {
+                WalletKind::Single => "SINGLE",
+                WalletKind::Multi => "MULTI",
+            }
+            .to_string(),
+            script_type,
+            default_policy,
+            keystores,
+        })?)
+    }
+
+    /// Construct from a Sparrow wallet in its JSON format. Private keys in the export are ignored.
+    pub fn from_sparrow_json(json: &str) -> Result<Self, Electrum2DescriptorError> {
+        let wallet: SparrowWallet = serde_json::from_str(json)?;
+        let wallet_kind = match wallet.policy_type.as_str() {
+            "SINGLE" => WalletKind::Single,
+            "MULTI" => WalletKind::Multi,
+            policy_type => {
+                return Err(Electrum2DescriptorError::InvalidSparrowWallet(
+                    policy_type.to_string(),
+                ))
+            }
+        };
+        let kind = SCRIPT_TYPES
+            .iter()
+            .find(|(script_type, _, wk)| *script_type == wallet.script_type && *wk == wallet_kind)
+            .map(|(_, kind, _)| *kind)
+            .ok_or_else(|| {
+                Electrum2DescriptorError::UnknownScriptKind(wallet.script_type.clone())
+            })?;
+        let threshold = match wallet_kind {
+            WalletKind::Single => 1,
+            WalletKind::Multi => {
+                let script = &wallet.default_policy.miniscript.script;
+                let captures = Regex::new(r#"multi\((\d+),"#)?
+                    .captures(script)
+                    .ok_or_else(|| {
+                        Electrum2DescriptorError::InvalidSparrowWallet(script.clone())
+                    })?;
+                captures[1]
+                    .parse()
+                    .map_err(|_| Electrum2DescriptorError::InvalidSparrowWallet(script.clone()))?
+            }
+        };
+
+        let keystores = wallet
+            .keystores
+            .iter()
+            .map(|ks| {
+                let derivation = &ks.key_derivation;
+                let fingerprint =
+                    Fingerprint::from_str(&derivation.master_fingerprint).map_err(|_| {
+                        Electrum2DescriptorError::InvalidKeyOrigin(
+                            derivation.master_fingerprint.clone(),
+                        )
+                    })?;
+                let xpub = parse_xpub(&ks.extended_public_key)?;
+                Ok(Keystore::new(kind, &xpub.to_string())?.with_origin(
+                    fingerprint,
+                    &DerivationPath::from_str(&derivation.derivation_path)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, Electrum2DescriptorError>>()?;
+        if (wallet_kind == WalletKind::Single) != (keystores.len() == 1) {
+            return Err(Electrum2DescriptorError::InvalidSparrowWallet(format!(
+                "{} keystores for policy type {}",
+                keystores.len(),
+                wallet.policy_type
+            )));
+        }
+        ElectrumWalletFile::new(&keystores, threshold)
+    }
+}
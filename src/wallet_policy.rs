@@ -0,0 +1,164 @@
+//! BIP-388 wallet policies, as used to register wallets on policy based signers like Ledger
+use crate::electrum_wallet_file::KEY_EXPRESSION;
+use crate::{
+    Electrum2DescriptorError, ElectrumExtendedKey, ElectrumExtendedPubKey, ElectrumWalletFile,
+    KeyPolicy,
+};
+use bitcoin::bip32::Xpub;
+use regex::{Captures, Regex};
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+/// A BIP-388 wallet policy: a descriptor template like `wsh(sortedmulti(2,@0/**,@1/**))` and the keys with origins `[fingerprint/derivation]xpub` the placeholders stand for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WalletPolicy {
+    pub template: String,
+    pub keys: Vec<String>,
+}
+
+impl WalletPolicy {
+    /// Constructs a new instance
+    pub fn new(template: String, keys: Vec<String>) -> Self {
+        WalletPolicy { template, keys }
+    }
+
+    /// Construct from an external descriptor whose keys end in `/0/*`
+    fn from_descriptor(desc: &str) -> Result<Self, Electrum2DescriptorError> {
+        let re = Regex::new(&format!(r#"({})/0/\*"#, KEY_EXPRESSION))?;
+        let mut keys = Vec::new();
+        let template = re
+            .replace_all(desc, |caps: &Captures| {
+                keys.push(caps[1].to_string());
+                format!("@{}/**", keys.len() - 1)
+            })
+            .to_string();
+        Ok(WalletPolicy { template, keys })
+    }
+
+    /// Returns the external descriptor by replacing the placeholders `@i/**` with the keys followed by `/0/*`
+    pub fn to_descriptor(&self) -> Result<String, Electrum2DescriptorError> {
+        let re = Regex::new(r#"@(\d+)/\*\*"#)?;
+        let placeholders = re
+            .captures_iter(&self.template)
+            .map(|caps| caps[1].parse::<usize>())
+            .collect::<Result<BTreeSet<_>, _>>()
+            .map_err(|_| Electrum2DescriptorError::InvalidWalletPolicy(self.template.clone()))?;
+        if placeholders != (0..self.keys.len()).collect() {
+            return Err(Electrum2DescriptorError::InvalidWalletPolicy(format!(
+                "{} doesn't use the {} keys",
+                self.template,
+                self.keys.len()
+            )));
+        }
+        Ok(re
+            .replace_all(&self.template, |caps: &Captures| {
+                format!("{}/0/*", self.keys[caps[1].parse::<usize>().unwrap()])
+            })
+            .to_string())
+    }
+}
+
+impl ElectrumWalletFile {
+    /// Convert to a BIP-388 wallet policy. Only xpubs are used, even if the wallet contains xprvs.
+    pub fn to_wallet_policy(&self) -> Result<WalletPolicy, Electrum2DescriptorError> {
+        WalletPolicy::from_descriptor(
+            &self
+                .to_descriptors_with_policy(KeyPolicy::PublicOnly, true)?
+                .external,
+        )
+    }
+
+    /// Construct from a BIP-388 wallet policy
+    pub fn from_wallet_policy(policy: &WalletPolicy) -> Result<Self, Electrum2DescriptorError> {
+        ElectrumWalletFile::from_descriptor(&policy.to_descriptor()?)
+    }
+}
+
+impl ElectrumExtendedPubKey {
+    /// Convert to a single signature BIP-388 wallet policy. The key has no origin, as it is unknown.
+    pub fn to_wallet_policy(&self) -> Result<WalletPolicy, Electrum2DescriptorError> {
+        WalletPolicy::from_descriptor(&self.to_descriptors()?.external)
+    }
+
+    /// Construct from a single signature BIP-388 wallet policy. The key origin is dropped.
+    pub fn from_wallet_policy(policy: &WalletPolicy) -> Result<Self, Electrum2DescriptorError> {
+        let re = Regex::new(r#"^(pkh|sh\(wpkh|wpkh|tr)\(@0/\*\*\)+$"#)?;
+        let kind = re
+            .captures(&policy.template)
+            .map(|caps| caps[1].to_string())
+            .ok_or_else(|| {
+                Electrum2DescriptorError::InvalidWalletPolicy(policy.template.clone())
+            })?;
+        let key = match &policy.keys[..] {
+            [key] => key,
+            _ => {
+                return Err(Electrum2DescriptorError::InvalidWalletPolicy(format!(
+                    "{} keys for a single signature policy",
+                    policy.keys.len()
+                )))
+            }
+        };
+        let xpub = key.rsplit(']').next().unwrap_or(key);
+        let expub = ElectrumExtendedPubKey::new(Xpub::from_str(xpub)?, kind);
+        if expub.to_wallet_policy()?.template != policy.template {
+            return Err(Electrum2DescriptorError::InvalidWalletPolicy(
+                policy.template.clone(),
+            ));
+        }
+        Ok(expub)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_singlesig_policy() {
+        let electrum_xpub = ElectrumExtendedPubKey::from_str("vpub5VXaSncXqxLbdmvrC4Y8z9CszPwuEscADoetWhfrxDFzPUbL5nbVtanYDkrVEutkv9n5A5aCcvRC9swbjDKgHjCZ2tAeae8VsBuPbS8KpXv").unwrap();
+        let policy = electrum_xpub.to_wallet_policy().unwrap();
+        assert_eq!(policy.template, "wpkh(@0/**)");
+        assert_eq!(policy.keys, vec!["tpubD9ZjaMn3rbP1cAVwJy6UcEjFfTLT7W6DbfHdS3Wn48meExtVfKmiH9meWCrSmE9qXLYbGcHC5LxLcdfLZTzwme23qAJoRzRhzbd68dHeyjp"]);
+        assert_eq!(
+            policy.to_descriptor().unwrap(),
+            electrum_xpub.to_descriptors().unwrap().external
+        );
+
+        let with_origin = WalletPolicy::new(
+            "wpkh(@0/**)".to_string(),
+            vec!["[d34db33f/84'/1'/0']tpubD9ZjaMn3rbP1cAVwJy6UcEjFfTLT7W6DbfHdS3Wn48meExtVfKmiH9meWCrSmE9qXLYbGcHC5LxLcdfLZTzwme23qAJoRzRhzbd68dHeyjp".to_string()],
+        );
+        let imported = ElectrumExtendedPubKey::from_wallet_policy(&with_origin).unwrap();
+        assert_eq!(
+            imported.electrum_xpub().unwrap(),
+            electrum_xpub.electrum_xpub().unwrap()
+        );
+
+        let taproot = ElectrumExtendedPubKey::from_wallet_policy(&WalletPolicy::new(
+            "tr(@0/**)".to_string(),
+            policy.keys.clone(),
+        ))
+        .unwrap();
+        assert_eq!(taproot.kind(), "tr");
+    }
+
+    #[test]
+    fn test_invalid_policy() {
+        let key = "tpubD9ZjaMn3rbP1cAVwJy6UcEjFfTLT7W6DbfHdS3Wn48meExtVfKmiH9meWCrSmE9qXLYbGcHC5LxLcdfLZTzwme23qAJoRzRhzbd68dHeyjp".to_string();
+        let unused_key =
+            WalletPolicy::new("wpkh(@0/**)".to_string(), vec![key.clone(), key.clone()]);
+        assert!(matches!(
+            unused_key.to_descriptor(),
+            Err(Electrum2DescriptorError::InvalidWalletPolicy(_))
+        ));
+        assert!(matches!(
+            ElectrumExtendedPubKey::from_wallet_policy(&unused_key),
+            Err(Electrum2DescriptorError::InvalidWalletPolicy(_))
+        ));
+        let multisig = WalletPolicy::new("wsh(sortedmulti(1,@0/**))".to_string(), vec![key]);
+        assert!(matches!(
+            ElectrumExtendedPubKey::from_wallet_policy(&multisig),
+            Err(Electrum2DescriptorError::InvalidWalletPolicy(_))
+        ));
+    }
+}
@@ -1,6 +1,18 @@
 #![cfg(feature = "wallet_file")]
-use bdk_wallet::{bitcoin::Network, KeychainKind, Wallet};
-use libelectrum2descriptors::{Descriptors, ElectrumWalletFile};
+use bdk_wallet::bitcoin::bip32::{DerivationPath, Fingerprint, Xpriv, Xpub};
+use bdk_wallet::{
+    bitcoin::{Network, ScriptBuf},
+    KeychainKind, Wallet,
+};
+use libelectrum2descriptors::{
+    bitcoin_core::{ListDescriptors, Timestamp},
+    checksum::with_checksum,
+    electrum_scripthash,
+    electrum_wallet_file::Keystore,
+    find_addresses, AddressMatch, AddressSource, Descriptors, Electrum2DescriptorError,
+    ElectrumExtendedPubKey, ElectrumWalletFile, KeyPolicy, MultisigBuilder,
+};
+use miniscript::{Descriptor, DescriptorPublicKey};
 use rstest::rstest;
 use std::{
     path::{Path, PathBuf},
@@ -78,6 +90,19 @@ fn first_address_from_wallet_file(wallet_name: &str) -> String {
     wallet.addresses().receiving[0].clone()
 }
 
+/// Runs the command line tool and returns whether it succeeded, its stdout and its stderr
+fn run_cli(args: &[&str]) -> (bool, String, String) {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_electrum2descriptors"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
 /// Since converting a wallet with imported keys or addresses can't be converted to a descriptor anyway, we just leave a not so descriptive error message due to a different json format of such wallets.
 #[rstest]
 #[case::imported_addr("imported_addr")]
@@ -144,3 +169,1235 @@ fn descriptor_string_roundtrip(#[case] descriptor: &str) {
     let desc = wallet.to_descriptors().unwrap();
     assert_eq!(desc.external, descriptor);
 }
+
+#[rstest]
+#[case::default_legacy("default_legacy")]
+#[case::default_legacy_watch("default_legacy_watch")]
+#[case::default_segwit("default_segwit")]
+#[case::multisig_hw_segwit("multisig_hw_segwit")]
+#[case::multisig_legacy("multisig_legacy")]
+#[case::multisig_segwit("multisig_segwit")]
+#[case::multisig_wrapped_watch("multisig_wrapped_watch")]
+fn verify_addresses(#[case] wallet_name: &str) {
+    let wallet_file = get_test_wallet_file(wallet_name);
+    let wallet = ElectrumWalletFile::from_file(wallet_file.as_path()).unwrap();
+    assert!(!wallet.addresses().receiving.is_empty());
+    assert!(!wallet.addresses().change.is_empty());
+    assert_eq!(wallet.verify_addresses().unwrap(), vec![]);
+}
+
+#[test]
+fn verify_addresses_mismatch() {
+    let wallet_file = get_test_wallet_file("default_segwit");
+    let mut json: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(wallet_file).unwrap()).unwrap();
+    let change = json["addresses"]["change"].as_array_mut().unwrap();
+    let expected = change[3].as_str().unwrap().to_string();
+    change[3] = "tb1qt63km8fkfuwnl6hzfktu7g2nh8tc6jhr6vequq".into();
+    let wallet = ElectrumWalletFile::from_str(&json.to_string()).unwrap();
+
+    let mismatches = wallet.verify_addresses().unwrap();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].chain, 1);
+    assert_eq!(mismatches[0].index, 3);
+    assert_eq!(mismatches[0].expected, expected);
+    assert_eq!(
+        mismatches[0].found,
+        "tb1qt63km8fkfuwnl6hzfktu7g2nh8tc6jhr6vequq"
+    );
+}
+
+#[rstest]
+#[case::default_legacy("default_legacy")]
+#[case::default_segwit("default_segwit")]
+#[case::multisig_hw_segwit("multisig_hw_segwit")]
+#[case::multisig_legacy("multisig_legacy")]
+#[case::multisig_wrapped_watch("multisig_wrapped_watch")]
+fn generate_addresses(#[case] wallet_name: &str) {
+    let wallet_file = get_test_wallet_file(wallet_name);
+    let electrum = ElectrumWalletFile::from_file(wallet_file.as_path()).unwrap();
+    let desc = electrum.to_descriptors().unwrap();
+
+    let mut wallet = ElectrumWalletFile::from_descriptor(&desc.external).unwrap();
+    assert_eq!(wallet.addresses().receiving.len(), 20);
+    assert_eq!(wallet.addresses().change.len(), 10);
+    assert_eq!(
+        wallet.addresses().receiving[..],
+        electrum.addresses().receiving[..20]
+    );
+    assert_eq!(
+        wallet.addresses().change[..],
+        electrum.addresses().change[..10]
+    );
+
+    wallet.generate_addresses(3, 2).unwrap();
+    assert_eq!(
+        wallet.addresses().receiving[..],
+        electrum.addresses().receiving[..3]
+    );
+    assert_eq!(
+        wallet.addresses().change[..],
+        electrum.addresses().change[..2]
+    );
+}
+
+#[test]
+fn find_addresses_in_wallets() {
+    let wallets = [
+        "default_segwit",
+        "multisig_legacy",
+        "multisig_wrapped_watch",
+    ]
+    .map(|name| ElectrumWalletFile::from_file(&get_test_wallet_file(name)).unwrap());
+    let wanted = [
+        wallets[1].addresses().change[4].clone(),
+        wallets[2].addresses().receiving[7].clone(),
+        wallets[0].addresses().receiving[0].clone(),
+        // first receiving address of tests/wallets/default_legacy, which is not searched
+        "muVsjryQ7q1YvQ9Ji7MqrxNAnSSMccDYeS".to_string(),
+    ];
+    let wanted = wanted.iter().map(|a| a.as_str()).collect::<Vec<_>>();
+    let sources = wallets
+        .iter()
+        .map(|w| w as &dyn AddressSource)
+        .collect::<Vec<_>>();
+
+    let found = find_addresses(&wanted, &sources, 20).unwrap();
+    assert_eq!(
+        found,
+        vec![
+            AddressMatch {
+                address: wanted[2].to_string(),
+                source: 0,
+                chain: 0,
+                index: 0
+            },
+            AddressMatch {
+                address: wanted[0].to_string(),
+                source: 1,
+                chain: 1,
+                index: 4
+            },
+            AddressMatch {
+                address: wanted[1].to_string(),
+                source: 2,
+                chain: 0,
+                index: 7
+            },
+        ]
+    );
+
+    // the same scriptPubKey encoded for mainnet doesn't belong to the testnet wallets
+    let script = wallets[0].script_pubkey(0, 0).unwrap();
+    let mainnet = bdk_wallet::bitcoin::Address::from_script(&script, Network::Bitcoin)
+        .unwrap()
+        .to_string();
+    assert!(matches!(
+        find_addresses(&[&mainnet], &sources, 20),
+        Err(Electrum2DescriptorError::AddressParseError(_))
+    ));
+    let regtest = bdk_wallet::bitcoin::Address::from_script(&script, Network::Regtest)
+        .unwrap()
+        .to_string();
+    assert_eq!(find_addresses(&[&regtest], &sources, 20).unwrap().len(), 1);
+}
+
+#[test]
+fn taproot() {
+    let wallet = ElectrumWalletFile::from_file(&get_test_wallet_file("default_segwit")).unwrap();
+    let desc = wallet
+        .to_taproot_descriptors(KeyPolicy::PrivateWhereAvailable)
+        .unwrap();
+    assert_eq!(desc.external, "tr(tprv8cvkZzx9zA7EfFDbH945mK23r7hg6EHXUk79wVUSRukwyctFS1AdpSpkZcykAMDveCj8RA3R4jwFTKMwMbWexJox8NMqq7YphJLDumfCSfu/0/*)");
+    assert_eq!(desc.change, "tr(tprv8cvkZzx9zA7EfFDbH945mK23r7hg6EHXUk79wVUSRukwyctFS1AdpSpkZcykAMDveCj8RA3R4jwFTKMwMbWexJox8NMqq7YphJLDumfCSfu/1/*)");
+    let addr = first_address_from_descriptor(&desc.external, Network::Testnet);
+    assert!(addr.starts_with("tb1p"));
+
+    assert!(matches!(
+        ElectrumWalletFile::from_descriptor(&desc.external),
+        Err(Electrum2DescriptorError::ElectrumUnsupportedScriptKind(kind)) if kind == "tr"
+    ));
+
+    let public = wallet
+        .to_taproot_descriptors(KeyPolicy::PublicOnly)
+        .unwrap();
+    assert!(public.external.starts_with("tr(tpub"));
+    let watch_only =
+        ElectrumWalletFile::from_file(&get_test_wallet_file("default_legacy_watch")).unwrap();
+    assert!(matches!(
+        watch_only.to_taproot_descriptors(KeyPolicy::RequirePrivate),
+        Err(Electrum2DescriptorError::MissingPrivateKey(0))
+    ));
+
+    let multisig = ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_segwit")).unwrap();
+    assert!(matches!(
+        multisig.to_taproot_descriptors(KeyPolicy::PublicOnly),
+        Err(Electrum2DescriptorError::TaprootMultisig)
+    ));
+}
+
+fn get_test_setup_file(file_name: &str) -> String {
+    let test_dir = Path::new(file!()).canonicalize().unwrap();
+    let setup_file = test_dir
+        .parent()
+        .unwrap()
+        .join("multisig_setups")
+        .join(file_name);
+    std::fs::read_to_string(setup_file).unwrap()
+}
+
+#[rstest]
+#[case::coldcard("coldcard_hw_segwit.txt")]
+#[case::specter("specter_hw_segwit.json")]
+#[case::sparrow("sparrow_hw_segwit.json")]
+fn import_multisig_setup(#[case] file_name: &str) {
+    let setup = get_test_setup_file(file_name);
+    let wallet = if file_name.starts_with("sparrow") {
+        ElectrumWalletFile::from_sparrow_json(&setup).unwrap()
+    } else if file_name.ends_with(".json") {
+        ElectrumWalletFile::from_specter_json(&setup).unwrap()
+    } else {
+        ElectrumWalletFile::from_coldcard_multisig(&setup).unwrap()
+    };
+    let electrum =
+        ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_hw_segwit")).unwrap();
+
+    assert_eq!(wallet.wallet_type(), electrum.wallet_type());
+    assert_eq!(
+        wallet.to_descriptors().unwrap(),
+        electrum.to_descriptors().unwrap()
+    );
+    assert_eq!(
+        wallet.to_descriptors_with_origins().unwrap(),
+        electrum.to_descriptors_with_origins().unwrap()
+    );
+    assert_eq!(wallet.to_descriptors_with_origins().unwrap().external, "wsh(sortedmulti(2,[27d81095/48'/1'/0'/2']tpubDEcw4ooTbmw62zBKdkYepoP3z4WWugdeRzPHHAbk8XVsPfBE9AAZMNghiqwtdFgtabaeppBTPmezUkRkQZidLcSJp3XTASbMakHcYauWehZ/0/*,[6bfac2d6/48'/100'/0'/2']tpubDEbkvhmJoZMq3SUNqEf3aEsubvqsCUPc7rroHkGERgS7qA1gQVMxUPrgzth6x43odirLohwf4aMHpvcnWi3jCB2xkizv8T4B2KqLRZVLC6K/0/*))");
+    for (keystore, expected) in wallet.keystores().iter().zip(electrum.keystores()) {
+        assert_eq!(keystore.xpub, expected.xpub);
+        assert_eq!(keystore.derivation, expected.derivation);
+        assert_eq!(keystore.root_fingerprint, expected.root_fingerprint);
+    }
+    assert_eq!(
+        wallet.addresses().receiving[..],
+        electrum.addresses().receiving[..20]
+    );
+}
+
+#[rstest]
+#[case::missing_policy("Format: P2WSH\nDerivation: m/48'/1'/0'/2'\n27D81095: tpubDEcw4ooTbmw62zBKdkYepoP3z4WWugdeRzPHHAbk8XVsPfBE9AAZMNghiqwtdFgtabaeppBTPmezUkRkQZidLcSJp3XTASbMakHcYauWehZ")]
+#[case::missing_derivation("Policy: 1 of 1\n27D81095: tpubDEcw4ooTbmw62zBKdkYepoP3z4WWugdeRzPHHAbk8XVsPfBE9AAZMNghiqwtdFgtabaeppBTPmezUkRkQZidLcSJp3XTASbMakHcYauWehZ")]
+#[case::garbage("Policy: 2 of 2\nnot a key")]
+fn import_invalid_coldcard_multisig(#[case] setup: &str) {
+    assert!(matches!(
+        ElectrumWalletFile::from_coldcard_multisig(setup),
+        Err(Electrum2DescriptorError::InvalidMultisigSetup(_))
+    ));
+}
+
+#[test]
+fn export_coldcard_multisig() {
+    let electrum =
+        ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_hw_segwit")).unwrap();
+    let setup = electrum.to_coldcard_multisig("hw_segwit").unwrap();
+    assert_eq!(
+        setup,
+        get_test_setup_file("coldcard_hw_segwit.txt").replace(
+            "(exported from Sparrow)",
+            "(exported by electrum2descriptors)"
+        )
+    );
+
+    let imported = ElectrumWalletFile::from_coldcard_multisig(&setup).unwrap();
+    assert_eq!(
+        imported.to_descriptors_with_origins().unwrap(),
+        electrum.to_descriptors_with_origins().unwrap()
+    );
+}
+
+#[test]
+fn export_coldcard_multisig_master_origin() {
+    let secp = bdk_wallet::bitcoin::secp256k1::Secp256k1::new();
+    let keys = [1u8, 2].map(|seed| {
+        let master = Xpriv::new_master(Network::Testnet, &[seed; 32]).unwrap();
+        format!(
+            "[{}]{}/0/*",
+            master.fingerprint(&secp),
+            Xpub::from_priv(&secp, &master)
+        )
+    });
+    let electrum = ElectrumWalletFile::from_descriptor(&format!(
+        "wsh(sortedmulti(1,{},{}))",
+        keys[0], keys[1]
+    ))
+    .unwrap();
+    let setup = electrum.to_coldcard_multisig("master").unwrap();
+    assert_eq!(setup.matches("\nDerivation: m\n").count(), 2, "{}", setup);
+
+    let imported = ElectrumWalletFile::from_coldcard_multisig(&setup).unwrap();
+    assert_eq!(
+        imported.to_descriptors_with_origins().unwrap(),
+        electrum.to_descriptors_with_origins().unwrap()
+    );
+}
+
+#[test]
+fn export_coldcard_multisig_errors() {
+    let without_origins = ElectrumWalletFile::from_descriptor("wsh(sortedmulti(2,tpubDEcw4ooTbmw62zBKdkYepoP3z4WWugdeRzPHHAbk8XVsPfBE9AAZMNghiqwtdFgtabaeppBTPmezUkRkQZidLcSJp3XTASbMakHcYauWehZ/0/*,tpubDEbkvhmJoZMq3SUNqEf3aEsubvqsCUPc7rroHkGERgS7qA1gQVMxUPrgzth6x43odirLohwf4aMHpvcnWi3jCB2xkizv8T4B2KqLRZVLC6K/0/*))").unwrap();
+    assert!(matches!(
+        without_origins.to_coldcard_multisig("test"),
+        Err(Electrum2DescriptorError::MissingKeyOrigin(0))
+    ));
+
+    let singlesig = ElectrumWalletFile::from_file(&get_test_wallet_file("default_segwit")).unwrap();
+    assert!(matches!(
+        singlesig.to_coldcard_multisig("test"),
+        Err(Electrum2DescriptorError::NotMultisig)
+    ));
+}
+
+#[test]
+fn bsms_roundtrip() {
+    let electrum =
+        ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_hw_segwit")).unwrap();
+    let record = electrum.to_bsms().unwrap();
+    assert_eq!(record, format!("BSMS 1.0\nwsh(sortedmulti(2,[27d81095/48'/1'/0'/2']tpubDEcw4ooTbmw62zBKdkYepoP3z4WWugdeRzPHHAbk8XVsPfBE9AAZMNghiqwtdFgtabaeppBTPmezUkRkQZidLcSJp3XTASbMakHcYauWehZ/**,[6bfac2d6/48'/100'/0'/2']tpubDEbkvhmJoZMq3SUNqEf3aEsubvqsCUPc7rroHkGERgS7qA1gQVMxUPrgzth6x43odirLohwf4aMHpvcnWi3jCB2xkizv8T4B2KqLRZVLC6K/**))\n/0/*,/1/*\n{}\n", electrum.addresses().receiving[0]));
+
+    let imported = ElectrumWalletFile::from_bsms(&record).unwrap();
+    assert_eq!(
+        imported.to_descriptors_with_origins().unwrap(),
+        electrum.to_descriptors_with_origins().unwrap()
+    );
+
+    let tampered = record.replace(
+        &electrum.addresses().receiving[0],
+        &electrum.addresses().receiving[1],
+    );
+    assert!(matches!(
+        ElectrumWalletFile::from_bsms(&tampered),
+        Err(Electrum2DescriptorError::FirstAddressMismatch(expected, found))
+            if expected == electrum.addresses().receiving[0] && found == electrum.addresses().receiving[1]
+    ));
+
+    // the first address encoded for mainnet doesn't match the testnet keys
+    let script = electrum.script_pubkey(0, 0).unwrap();
+    let mainnet = bdk_wallet::bitcoin::Address::from_script(&script, Network::Bitcoin).unwrap();
+    let mainnet = record.replace(&electrum.addresses().receiving[0], &mainnet.to_string());
+    assert!(matches!(
+        ElectrumWalletFile::from_bsms(&mainnet),
+        Err(Electrum2DescriptorError::AddressParseError(_))
+    ));
+
+    let restricted = record.replace("/0/*,/1/*", "/0/*,/2/*");
+    assert!(matches!(
+        ElectrumWalletFile::from_bsms(&restricted),
+        Err(Electrum2DescriptorError::InvalidBsmsRecord(_))
+    ));
+}
+
+#[test]
+fn bsms_exports_public_keys_only() {
+    let electrum = ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_segwit")).unwrap();
+    assert!(electrum.keystores()[0].xprv.is_some());
+    let record = electrum.to_bsms().unwrap();
+    assert!(!record.contains("prv"));
+    let imported = ElectrumWalletFile::from_bsms(&record).unwrap();
+    assert_eq!(imported.addresses(), electrum.addresses());
+}
+
+#[test]
+fn wallet_policy_roundtrip() {
+    let electrum = ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_segwit")).unwrap();
+    let policy = electrum.to_wallet_policy().unwrap();
+    assert_eq!(policy.template, "wsh(sortedmulti(2,@0/**,@1/**))");
+    assert_eq!(policy.keys.len(), 2);
+    assert!(policy.keys[0].starts_with("[f6083804/1']tpub"));
+    assert!(policy.keys[1].starts_with("[b88448fb/1']tpub"));
+
+    let imported = ElectrumWalletFile::from_wallet_policy(&policy).unwrap();
+    assert_eq!(imported.addresses(), electrum.addresses());
+    assert_eq!(imported.to_wallet_policy().unwrap(), policy);
+    assert_eq!(
+        policy.to_descriptor().unwrap(),
+        imported.to_descriptors_with_origins().unwrap().external
+    );
+}
+
+#[test]
+fn bitcoin_core_requests() {
+    let electrum = ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_segwit")).unwrap();
+    let createwallet = electrum
+        .to_core_createwallet("segwit", KeyPolicy::PublicOnly)
+        .unwrap();
+    assert_eq!(
+        createwallet.to_json().unwrap(),
+        r#"{"jsonrpc":"1.0","id":"electrum2descriptors","method":"createwallet","params":{"wallet_name":"segwit","disable_private_keys":true,"blank":true,"descriptors":true}}"#
+    );
+
+    let import = electrum
+        .to_core_importdescriptors(
+            Some(Timestamp::Time(1_600_000_000)),
+            1000,
+            KeyPolicy::PublicOnly,
+        )
+        .unwrap();
+    let requests = &import.params.0;
+    assert_eq!(requests.len(), 2);
+    for (request, internal) in requests.iter().zip([false, true]) {
+        let (desc, _checksum) = request.desc.split_once('#').unwrap();
+        assert_eq!(request.desc, with_checksum(desc).unwrap());
+        assert!(!desc.contains("prv"));
+        assert_eq!(desc.contains("/1/*"), internal);
+        assert!(request.active);
+        assert_eq!(request.internal, internal);
+        assert_eq!(request.range, [0, 999]);
+    }
+    let json = import.to_json().unwrap();
+    assert!(json.contains(
+        r#""method":"importdescriptors","params":[[{"desc":"wsh(sortedmulti(2,[f6083804/1']tpub"#
+    ));
+    assert!(json.contains(r#""timestamp":1600000000"#));
+
+    let private = electrum
+        .to_core_importdescriptors(Some(Timestamp::Now), 20, KeyPolicy::PrivateWhereAvailable)
+        .unwrap();
+    assert!(private.params.0[0].desc.contains("tprv"));
+    assert!(private.to_json().unwrap().contains(r#""timestamp":"now""#));
+    assert!(
+        !electrum
+            .to_core_createwallet("segwit", KeyPolicy::PrivateWhereAvailable)
+            .unwrap()
+            .params
+            .disable_private_keys
+    );
+    assert!(matches!(
+        electrum.to_core_importdescriptors(None, 20, KeyPolicy::RequirePrivate),
+        Err(Electrum2DescriptorError::MissingPrivateKey(1))
+    ));
+
+    let watch_only =
+        ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_hw_segwit")).unwrap();
+    assert!(
+        watch_only
+            .to_core_createwallet("watch", KeyPolicy::PrivateWhereAvailable)
+            .unwrap()
+            .params
+            .disable_private_keys
+    );
+    let watch_only =
+        ElectrumWalletFile::from_file(&get_test_wallet_file("default_legacy_watch")).unwrap();
+    assert!(
+        watch_only
+            .to_core_createwallet("watch", KeyPolicy::PrivateWhereAvailable)
+            .unwrap()
+            .params
+            .disable_private_keys
+    );
+}
+
+#[rstest]
+#[case::public_only(KeyPolicy::PublicOnly, Ok([false, false]))]
+#[case::private_where_available(KeyPolicy::PrivateWhereAvailable, Ok([true, false]))]
+#[case::require_private(KeyPolicy::RequirePrivate, Err(1))]
+fn key_policy(#[case] policy: KeyPolicy, #[case] expected: Result<[bool; 2], usize>) {
+    let electrum = ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_segwit")).unwrap();
+    match (electrum.to_descriptors_with_policy(policy, false), expected) {
+        (Ok(descriptors), Ok(private)) => {
+            let keys = descriptors.external["wsh(sortedmulti(2,".len()..]
+                .split(',')
+                .map(|key| key.starts_with("tprv"))
+                .collect::<Vec<_>>();
+            assert_eq!(keys, private);
+            assert_eq!(
+                descriptors.change,
+                descriptors.external.replace("/0/*", "/1/*")
+            );
+        }
+        (Err(Electrum2DescriptorError::MissingPrivateKey(i)), Err(expected)) => {
+            assert_eq!(i, expected)
+        }
+        (result, _) => panic!("unexpected {:?}", result),
+    }
+    assert_eq!(
+        electrum
+            .to_descriptors_with_policy(KeyPolicy::PrivateWhereAvailable, false)
+            .unwrap(),
+        electrum.to_descriptors().unwrap()
+    );
+
+    let full = ElectrumWalletFile::from_file(&get_test_wallet_file("default_segwit")).unwrap();
+    let descriptors = full.to_descriptors_with_policy(policy, true).unwrap();
+    assert_eq!(
+        descriptors.external.contains("tprv"),
+        policy != KeyPolicy::PublicOnly
+    );
+}
+
+#[cfg(feature = "bdk")]
+#[rstest]
+#[case::default_legacy("default_legacy")]
+#[case::default_segwit("default_segwit")]
+#[case::multisig_hw_segwit("multisig_hw_segwit")]
+#[case::multisig_legacy("multisig_legacy")]
+#[case::multisig_segwit("multisig_segwit")]
+#[case::multisig_wrapped_watch("multisig_wrapped_watch")]
+fn bdk_wallet_roundtrip(#[case] wallet_name: &str) {
+    let electrum = ElectrumWalletFile::from_file(&get_test_wallet_file(wallet_name)).unwrap();
+    let wallet = electrum.to_bdk_wallet(Network::Testnet).unwrap();
+    for (keychain, addresses) in [
+        (KeychainKind::External, &electrum.addresses().receiving),
+        (KeychainKind::Internal, &electrum.addresses().change),
+    ] {
+        for (index, address) in addresses.iter().enumerate() {
+            assert_eq!(
+                &wallet.peek_address(keychain, index as u32).to_string(),
+                address
+            );
+        }
+    }
+
+    let imported = ElectrumWalletFile::from_bdk_wallet(&wallet).unwrap();
+    let receiving = &imported.addresses().receiving;
+    assert_eq!(
+        receiving[..],
+        electrum.addresses().receiving[..receiving.len()]
+    );
+    assert!(imported.keystores().iter().all(|ks| ks.xprv.is_none()));
+}
+
+#[rstest]
+#[case::default_legacy("default_legacy")]
+#[case::default_segwit("default_segwit")]
+#[case::multisig_hw_segwit("multisig_hw_segwit")]
+#[case::multisig_segwit("multisig_segwit")]
+fn sparrow_roundtrip(#[case] wallet_name: &str) {
+    let electrum = ElectrumWalletFile::from_file(&get_test_wallet_file(wallet_name)).unwrap();
+    let json = electrum.to_sparrow_json(wallet_name).unwrap();
+    assert!(!json.contains("prv"));
+    let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+    for keystore in parsed["keystores"].as_array().unwrap() {
+        let path = keystore["keyDerivation"]["derivationPath"]
+            .as_str()
+            .unwrap();
+        assert!(path == "m" || !path.ends_with('/'), "{}", path);
+    }
+    if wallet_name == "default_legacy" {
+        // electrum's legacy wallets use the master key itself
+        assert_eq!(
+            parsed["keystores"][0]["keyDerivation"]["derivationPath"],
+            "m"
+        );
+    }
+    let imported = ElectrumWalletFile::from_sparrow_json(&json).unwrap();
+    assert_eq!(imported.wallet_type(), electrum.wallet_type());
+    assert_eq!(
+        imported.to_wallet_policy().unwrap(),
+        electrum.to_wallet_policy().unwrap()
+    );
+    let receiving = &imported.addresses().receiving;
+    assert_eq!(
+        receiving[..],
+        electrum.addresses().receiving[..receiving.len()]
+    );
+}
+
+#[test]
+fn sparrow_export_format() {
+    let electrum = ElectrumWalletFile::from_file(&get_test_wallet_file("default_segwit")).unwrap();
+    let json: serde_json::Value =
+        serde_json::from_str(&electrum.to_sparrow_json("segwit").unwrap()).unwrap();
+    assert_eq!(json["name"], "segwit");
+    assert_eq!(json["policyType"], "SINGLE");
+    assert_eq!(json["scriptType"], "P2WPKH");
+    assert_eq!(
+        json["defaultPolicy"]["miniscript"]["script"],
+        "wpkh(Keystore 1)"
+    );
+    assert_eq!(
+        json["keystores"][0]["keyDerivation"]["masterFingerprint"],
+        "b88448fb"
+    );
+    assert_eq!(
+        json["keystores"][0]["keyDerivation"]["derivationPath"],
+        "m/0'"
+    );
+
+    let setup = get_test_setup_file("sparrow_hw_segwit.json").replace("\"MULTI\"", "\"SINGLE\"");
+    assert!(matches!(
+        ElectrumWalletFile::from_sparrow_json(&setup),
+        Err(Electrum2DescriptorError::UnknownScriptKind(_))
+    ));
+}
+
+#[test]
+fn multisig_builder() {
+    let electrum = ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_segwit")).unwrap();
+    let origin = |fingerprint, path| {
+        Some((
+            Fingerprint::from_str(fingerprint).unwrap(),
+            DerivationPath::from_str(path).unwrap(),
+        ))
+    };
+    let cosigner = ElectrumExtendedPubKey::from_str("Vpub5gUii5ZKgrJs2sZfyHrKFrKdZqmek7iLGoX3jmREfdMscpyaDPLAcYm1mED7PexvD4JQPMJL3AGtMewxSjFhcUX64fKFR2bgLXAY7xPgJaX").unwrap();
+    let wallet = MultisigBuilder::new(2)
+        .slip132("Vprv1AEubNnoCJHpVL2TLDscvM6bEC7Q4ZxqkrdhxCHFb6EJtADGoSkb9cXt9tTFYqgEVSJGhM96eZmwK1G88SW2Zg1LayXACst8XFYSgS8dK8b", origin("f6083804", "m/1'"))
+        .unwrap()
+        .pub_key(&cosigner, origin("b88448fb", "m/1'"))
+        .build()
+        .unwrap();
+    assert_eq!(wallet.wallet_type(), electrum.wallet_type());
+    assert_eq!(
+        wallet.to_descriptors_with_origins().unwrap(),
+        electrum.to_descriptors_with_origins().unwrap()
+    );
+    assert_eq!(
+        wallet.addresses().receiving[..],
+        electrum.addresses().receiving[..wallet.addresses().receiving.len()]
+    );
+
+    let descriptors = MultisigBuilder::new(2)
+        .kind("wsh")
+        .bip32("[27d81095/48'/1'/0'/2']tpubDEcw4ooTbmw62zBKdkYepoP3z4WWugdeRzPHHAbk8XVsPfBE9AAZMNghiqwtdFgtabaeppBTPmezUkRkQZidLcSJp3XTASbMakHcYauWehZ")
+        .unwrap()
+        .bip32("[6bfac2d6/48'/100'/0'/2']tpubDEbkvhmJoZMq3SUNqEf3aEsubvqsCUPc7rroHkGERgS7qA1gQVMxUPrgzth6x43odirLohwf4aMHpvcnWi3jCB2xkizv8T4B2KqLRZVLC6K")
+        .unwrap()
+        .build_descriptors(KeyPolicy::PublicOnly)
+        .unwrap();
+    let hw_segwit =
+        ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_hw_segwit")).unwrap();
+    assert_eq!(
+        descriptors,
+        hw_segwit.to_descriptors_with_origins().unwrap()
+    );
+
+    assert!(matches!(
+        MultisigBuilder::new(1)
+            .kind("sh(wsh")
+            .pub_key(&cosigner, None)
+            .bip32("tpubDEcw4ooTbmw62zBKdkYepoP3z4WWugdeRzPHHAbk8XVsPfBE9AAZMNghiqwtdFgtabaeppBTPmezUkRkQZidLcSJp3XTASbMakHcYauWehZ")
+            .unwrap()
+            .build(),
+        Err(Electrum2DescriptorError::MixedScriptKinds(_, _))
+    ));
+    assert!(matches!(
+        MultisigBuilder::new(1)
+            .bip32("tpubDEcw4ooTbmw62zBKdkYepoP3z4WWugdeRzPHHAbk8XVsPfBE9AAZMNghiqwtdFgtabaeppBTPmezUkRkQZidLcSJp3XTASbMakHcYauWehZ")
+            .unwrap()
+            .bip32("xpub6BosfCnifzxcFwrSzQiqu2DBVTshkCXacvNsWGYJVVhhawA7d4R5WSWGFNbi8Aw6ZRc1brxMyWMzG3DSSSSoekkudhUd9yLb6qx39T9nMdj")
+            .unwrap()
+            .kind("wsh")
+            .build(),
+        Err(Electrum2DescriptorError::MixedNetworks)
+    ));
+    assert!(matches!(
+        MultisigBuilder::new(1)
+            .kind("wpkh")
+            .pub_key(&cosigner, None)
+            .pub_key(&cosigner, None)
+            .build(),
+        Err(Electrum2DescriptorError::SinglesigKindKey(_))
+    ));
+    assert!(matches!(
+        MultisigBuilder::new(3)
+            .pub_key(&cosigner, None)
+            .pub_key(&cosigner, None)
+            .build(),
+        Err(Electrum2DescriptorError::NumberSignaturesKeyStores(3, 2))
+    ));
+    assert!(matches!(
+        MultisigBuilder::new(1)
+            .bip32("tpubDEcw4ooTbmw62zBKdkYepoP3z4WWugdeRzPHHAbk8XVsPfBE9AAZMNghiqwtdFgtabaeppBTPmezUkRkQZidLcSJp3XTASbMakHcYauWehZ")
+            .unwrap()
+            .bip32("tpubDEbkvhmJoZMq3SUNqEf3aEsubvqsCUPc7rroHkGERgS7qA1gQVMxUPrgzth6x43odirLohwf4aMHpvcnWi3jCB2xkizv8T4B2KqLRZVLC6K")
+            .unwrap()
+            .build(),
+        Err(Electrum2DescriptorError::MissingScriptKind)
+    ));
+}
+
+#[test]
+fn multisig_keystore_consistency() {
+    let electrum = ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_segwit")).unwrap();
+    let keystores = electrum.keystores();
+    let tpub1 = "tpubDEcw4ooTbmw62zBKdkYepoP3z4WWugdeRzPHHAbk8XVsPfBE9AAZMNghiqwtdFgtabaeppBTPmezUkRkQZidLcSJp3XTASbMakHcYauWehZ";
+    let tpub2 = "tpubDEbkvhmJoZMq3SUNqEf3aEsubvqsCUPc7rroHkGERgS7qA1gQVMxUPrgzth6x43odirLohwf4aMHpvcnWi3jCB2xkizv8T4B2KqLRZVLC6K";
+    let xpub = "xpub6BosfCnifzxcFwrSzQiqu2DBVTshkCXacvNsWGYJVVhhawA7d4R5WSWGFNbi8Aw6ZRc1brxMyWMzG3DSSSSoekkudhUd9yLb6qx39T9nMdj";
+
+    assert!(ElectrumWalletFile::new(keystores, 2).is_ok());
+    assert!(matches!(
+        ElectrumWalletFile::new(keystores, 0),
+        Err(Electrum2DescriptorError::ZeroThreshold)
+    ));
+    assert!(matches!(
+        ElectrumWalletFile::new(
+            &[
+                Keystore::new("wsh", tpub1).unwrap(),
+                Keystore::new("sh(wsh", tpub2).unwrap()
+            ],
+            1
+        ),
+        Err(Electrum2DescriptorError::MixedScriptKinds(_, _))
+    ));
+    assert!(matches!(
+        ElectrumWalletFile::new(
+            &[
+                Keystore::new("wsh", tpub1).unwrap(),
+                Keystore::new("wsh", xpub).unwrap()
+            ],
+            1
+        ),
+        Err(Electrum2DescriptorError::MixedNetworks)
+    ));
+    assert!(matches!(
+        ElectrumWalletFile::new(
+            &[
+                Keystore::new("wsh", tpub1).unwrap(),
+                Keystore::new("wsh", tpub2).unwrap(),
+                Keystore::new("wsh", tpub1).unwrap()
+            ],
+            2
+        ),
+        Err(Electrum2DescriptorError::DuplicateCosigner(0, 2))
+    ));
+
+    let mut swapped = keystores.clone();
+    swapped[0].xpub = keystores[1].xpub.clone();
+    swapped[1].xpub = keystores[0].xpub.clone();
+    assert!(matches!(
+        ElectrumWalletFile::new(&swapped, 2),
+        Err(Electrum2DescriptorError::KeystoreKeyMismatch(0))
+    ));
+}
+
+#[test]
+fn many_cosigners() {
+    let secp = bdk_wallet::bitcoin::secp256k1::Secp256k1::new();
+    let cosigners = |n: u8| {
+        (1..=n).fold(MultisigBuilder::new(10).kind("wsh"), |builder, i| {
+            let xprv = Xpriv::new_master(Network::Testnet, &[i; 32]).unwrap();
+            builder
+                .bip32(&Xpub::from_priv(&secp, &xprv).to_string())
+                .unwrap()
+        })
+    };
+    let wallet = cosigners(12).build().unwrap();
+    assert_eq!(wallet.wallet_type().to_string(), "Multisig(10, 12)");
+
+    // serde_json sorts the keys, x10/ before x2/
+    let json: serde_json::Value = serde_json::from_str(&wallet.to_string()).unwrap();
+    let parsed = ElectrumWalletFile::from_str(&json.to_string()).unwrap();
+    assert_eq!(parsed.keystores(), wallet.keystores());
+
+    let desc = wallet.to_descriptors().unwrap();
+    assert!(desc.external.starts_with("wsh(sortedmulti(10,"));
+    let from_desc = ElectrumWalletFile::from_descriptor(&desc.external).unwrap();
+    assert_eq!(from_desc.keystores(), wallet.keystores());
+
+    let mut gap = json.clone();
+    gap.as_object_mut().unwrap().remove("x3/");
+    gap["wallet_type"] = "10of11".into();
+    let err = ElectrumWalletFile::from_str(&gap.to_string()).unwrap_err();
+    assert!(err.to_string().contains("x3/ is missing"), "{}", err);
+
+    let duplicate = wallet.to_string().replace("\"x12/\"", "\"x2/\"");
+    let err = ElectrumWalletFile::from_str(&duplicate).unwrap_err();
+    assert!(
+        err.to_string().contains("x2/ appears more than once"),
+        "{}",
+        err
+    );
+
+    assert!(matches!(
+        cosigners(16).build(),
+        Err(Electrum2DescriptorError::TooManyKeyStores(16))
+    ));
+}
+
+#[test]
+fn descriptor_derivation_steps() {
+    let secp = bdk_wallet::bitcoin::secp256k1::Secp256k1::new();
+    let master = Xpriv::new_master(Network::Testnet, &[7; 32]).unwrap();
+    let fingerprint = master.fingerprint(&secp);
+    let path = DerivationPath::from_str("84'/1'/0'").unwrap();
+    let account = Xpub::from_priv(&secp, &master.derive_priv(&secp, &path).unwrap());
+
+    let from_master =
+        ElectrumWalletFile::from_descriptor(&format!("wpkh({}/84'/1'/0'/0/*)", master)).unwrap();
+    let from_account = ElectrumWalletFile::from_descriptor(&format!(
+        "wpkh([{}/84'/1'/0']{}/0/*)",
+        fingerprint, account
+    ))
+    .unwrap();
+    assert_eq!(
+        from_master.keystores()[0].xpub,
+        from_account.keystores()[0].xpub
+    );
+    assert!(from_master.keystores()[0].xprv.is_some());
+    assert_eq!(
+        from_master
+            .to_descriptors_with_policy(KeyPolicy::PublicOnly, true)
+            .unwrap(),
+        from_account.to_descriptors_with_origins().unwrap()
+    );
+    assert_eq!(from_master.addresses(), from_account.addresses());
+
+    let from_steps = ElectrumWalletFile::from_descriptor(&format!(
+        "wpkh([{}/84'/1'/0']{}/5/0/*)",
+        fingerprint, account
+    ))
+    .unwrap();
+    assert_eq!(
+        from_steps.keystores()[0].origin().unwrap(),
+        Some((
+            fingerprint,
+            DerivationPath::from_str("84'/1'/0'/5").unwrap()
+        ))
+    );
+    // steps from an account key without origin don't tell where the key comes from
+    let unknown_origin =
+        ElectrumWalletFile::from_descriptor(&format!("wpkh({}/5/0/*)", account)).unwrap();
+    assert_eq!(unknown_origin.keystores()[0].origin().unwrap(), None);
+    assert_eq!(
+        unknown_origin.keystores()[0].xpub,
+        from_steps.keystores()[0].xpub
+    );
+
+    let cosigner = Xpriv::new_master(Network::Testnet, &[8; 32]).unwrap();
+    let multisig = ElectrumWalletFile::from_descriptor(&format!(
+        "wsh(sortedmulti(1,{}/48h/1h/0h/2h/0/*,{}/48h/1h/0h/2h/0/*))",
+        master, cosigner
+    ))
+    .unwrap();
+    let desc = multisig.to_descriptors_with_origins().unwrap();
+    assert!(desc
+        .external
+        .contains(&format!("[{}/48'/1'/0'/2']", fingerprint)));
+    assert!(desc
+        .external
+        .contains(&format!("[{}/48'/1'/0'/2']", cosigner.fingerprint(&secp))));
+
+    assert!(matches!(
+        ElectrumWalletFile::from_descriptor(&format!("wpkh({}/84'/1'/0'/0/*')", master)),
+        Err(Electrum2DescriptorError::HardenedWildcard(_))
+    ));
+    assert!(matches!(
+        ElectrumWalletFile::from_descriptor(&format!("wpkh({}/1'/0/*)", account)),
+        Err(Electrum2DescriptorError::Bip32Error(_))
+    ));
+}
+
+#[test]
+fn from_external_and_change_descriptor() {
+    let electrum =
+        ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_hw_segwit")).unwrap();
+    let desc = electrum.to_descriptors_with_origins().unwrap();
+    let wallet = ElectrumWalletFile::from_descriptors(
+        &with_checksum(&desc.external).unwrap(),
+        &with_checksum(&desc.change).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(wallet.to_descriptors_with_origins().unwrap(), desc);
+
+    for change in [
+        desc.external.clone(),
+        desc.external.replace("/0/*", "/2/*"),
+        desc.change.replacen("/1/*", "/0/*", 1),
+        desc.change.replace("wsh(", "sh(wsh("),
+        ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_segwit"))
+            .unwrap()
+            .to_descriptors()
+            .unwrap()
+            .change,
+    ] {
+        assert!(
+            matches!(
+                ElectrumWalletFile::from_descriptors(&desc.external, &change),
+                Err(Electrum2DescriptorError::ChangeDescriptorMismatch(_, _))
+            ),
+            "{}",
+            change
+        );
+    }
+}
+
+#[test]
+fn core_listdescriptors_import() {
+    let secp = bdk_wallet::bitcoin::secp256k1::Secp256k1::new();
+    let master = Xpriv::new_master(Network::Testnet, &[9; 32]).unwrap();
+    let fingerprint = master.fingerprint(&secp);
+    let listed = |script: &str, purpose: u32, internal: bool| {
+        let path = DerivationPath::from_str(&format!("{}h/1h/0h", purpose)).unwrap();
+        let account = master.derive_priv(&secp, &path).unwrap();
+        let desc = format!(
+            "{}([{}/{}h/1h/0h]{}/{}/*){}",
+            script,
+            fingerprint,
+            purpose,
+            account,
+            internal as u8,
+            ")".repeat(script.matches('(').count())
+        );
+        serde_json::json!({
+            "desc": with_checksum(&desc).unwrap(),
+            "timestamp": 1700000000,
+            "active": true,
+            "internal": internal,
+            "range": [0, 999],
+            "next": 25,
+            "next_index": 25,
+        })
+    };
+    let combo = format!("combo({})", Xpub::from_priv(&secp, &master));
+    let json = serde_json::json!({
+        "wallet_name": "hot",
+        "descriptors": [
+            listed("pkh", 44, false),
+            listed("sh(wpkh", 49, true),
+            listed("tr", 86, false),
+            listed("wpkh", 84, true),
+            listed("pkh", 44, true),
+            listed("sh(wpkh", 49, false),
+            listed("wpkh", 84, false),
+            listed("tr", 86, true),
+            { "desc": with_checksum(&combo).unwrap(), "timestamp": 1700000000, "active": false },
+        ],
+    });
+
+    let import = ListDescriptors::from_json(&json.to_string())
+        .unwrap()
+        .to_electrum_wallets();
+    let kinds = import
+        .wallets
+        .iter()
+        .map(|wallet| {
+            let desc = wallet.to_descriptors().unwrap().external;
+            desc[..desc.find("(tprv").unwrap()].to_string()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, vec!["pkh", "sh(wpkh", "wpkh"]);
+    for wallet in &import.wallets {
+        assert!(wallet.keystores()[0].xprv.is_some());
+        assert_eq!(wallet.addresses().receiving.len(), 25);
+        assert_eq!(wallet.addresses().change.len(), 25);
+        assert_eq!(
+            wallet.keystores()[0].root_fingerprint,
+            Some(fingerprint.to_string())
+        );
+    }
+
+    let skipped = import
+        .skipped
+        .iter()
+        .map(|s| s.desc.split('#').next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(skipped.len(), 3);
+    assert!(skipped[0].starts_with("tr(") && !skipped[0].contains("/1/*"));
+    assert!(skipped[1].starts_with("tr(") && skipped[1].contains("/1/*"));
+    assert_eq!(skipped[2], combo);
+    assert_eq!(import.skipped[2].reason, "inactive descriptor");
+}
+
+#[test]
+fn scripthashes() {
+    // example of the electrum protocol documentation
+    let script = ScriptBuf::from_hex("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac").unwrap();
+    assert_eq!(
+        electrum_scripthash(&script),
+        "8b01df4e368ea28f8dc0423bcf7a4923e3a12d307c875e47a0cfbf90b5c39161"
+    );
+
+    let electrum =
+        ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_hw_segwit")).unwrap();
+    let desc = electrum.to_descriptors().unwrap();
+    for (chain, desc, addresses) in [
+        (0, &desc.external, &electrum.addresses().receiving),
+        (1, &desc.change, &electrum.addresses().change),
+    ] {
+        let derived = electrum.scripthashes(chain, 2..5).unwrap();
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(desc).unwrap();
+        assert_eq!(derived.len(), 3);
+        for (derived, index) in derived.iter().zip(2..) {
+            assert_eq!(derived.chain, chain);
+            assert_eq!(derived.index, index);
+            assert_eq!(derived.address.to_string(), addresses[index as usize]);
+            assert_eq!(
+                derived.script_pubkey,
+                desc.at_derivation_index(index).unwrap().script_pubkey()
+            );
+            assert_eq!(
+                derived.scripthash,
+                electrum_scripthash(&derived.script_pubkey)
+            );
+        }
+    }
+
+    let expub = ElectrumExtendedPubKey::from_str("vpub5VXaSncXqxLbdmvrC4Y8z9CszPwuEscADoetWhfrxDFzPUbL5nbVtanYDkrVEutkv9n5A5aCcvRC9swbjDKgHjCZ2tAeae8VsBuPbS8KpXv").unwrap();
+    let derived = expub.scripthashes(1, 0..2).unwrap();
+    assert_eq!(derived[1].address, expub.address(1, 1).unwrap());
+}
+
+/// Serves `blockchain.scripthash.get_history` with one transaction for each of the `used` scripthashes
+#[cfg(feature = "electrum_server")]
+fn mock_electrum_server(used: Vec<String>) -> std::net::SocketAddr {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        for line in BufReader::new(stream).lines() {
+            let request: serde_json::Value = serde_json::from_str(&line.unwrap()).unwrap();
+            let result = match request["method"].as_str().unwrap() {
+                "server.version" => serde_json::json!(["mock", "1.4"]),
+                "blockchain.scripthash.get_history" => {
+                    if used.iter().any(|s| *s == request["params"][0]) {
+                        serde_json::json!([{ "tx_hash": "00".repeat(32), "height": 800000 }])
+                    } else {
+                        serde_json::json!([])
+                    }
+                }
+                _ => serde_json::Value::Null,
+            };
+            // a notification the client has to skip
+            writeln!(
+                writer,
+                r#"{{"jsonrpc":"2.0","method":"blockchain.headers.subscribe","params":[]}}"#
+            )
+            .unwrap();
+            let response =
+                serde_json::json!({ "jsonrpc": "2.0", "id": request["id"], "result": result });
+            writeln!(writer, "{}", response).unwrap();
+        }
+    });
+    address
+}
+
+#[cfg(feature = "electrum_server")]
+#[test]
+fn electrum_server_scan() {
+    use libelectrum2descriptors::electrum_server::{ElectrumServer, UsedIndexes, DEFAULT_TIMEOUT};
+
+    let electrum =
+        ElectrumWalletFile::from_file(&get_test_wallet_file("multisig_hw_segwit")).unwrap();
+    let scripthash = |chain, index| {
+        electrum.scripthashes(chain, index..index + 1).unwrap()[0]
+            .scripthash
+            .clone()
+    };
+    // index 44 is beyond the gap of 20 unused addresses after index 23
+    let used = vec![
+        scripthash(0, 3),
+        scripthash(0, 23),
+        scripthash(0, 44),
+        scripthash(1, 0),
+    ];
+    let mut server = ElectrumServer::connect(mock_electrum_server(used), DEFAULT_TIMEOUT).unwrap();
+
+    let indexes = server.scan(&electrum, 20).unwrap();
+    assert_eq!(
+        indexes,
+        UsedIndexes {
+            receiving: Some(23),
+            change: Some(0),
+        }
+    );
+    assert_eq!(indexes.import_range(20), 44);
+
+    let unused = ElectrumExtendedPubKey::from_str("vpub5VXaSncXqxLbdmvrC4Y8z9CszPwuEscADoetWhfrxDFzPUbL5nbVtanYDkrVEutkv9n5A5aCcvRC9swbjDKgHjCZ2tAeae8VsBuPbS8KpXv").unwrap();
+    let indexes = server.scan(&unused, 5).unwrap();
+    assert_eq!(indexes, UsedIndexes::default());
+    assert_eq!(indexes.import_range(5), 5);
+}
+
+/// Accepts connections but never answers
+#[cfg(feature = "electrum_server")]
+fn silent_electrum_server() -> std::net::SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let _connections = listener.incoming().collect::<Vec<_>>();
+    });
+    address
+}
+
+#[cfg(feature = "electrum_server")]
+#[test]
+fn electrum_server_timeout() {
+    use libelectrum2descriptors::electrum_server::ElectrumServer;
+    use std::time::{Duration, Instant};
+
+    let is_timeout = |err: &Electrum2DescriptorError| matches!(err, Electrum2DescriptorError::IO(err) if matches!(err.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut));
+    let start = Instant::now();
+    let err = ElectrumServer::connect(silent_electrum_server(), Duration::from_millis(200))
+        .err()
+        .unwrap();
+    assert!(is_timeout(&err), "{:?}", err);
+
+    #[cfg(feature = "electrum_server_tls")]
+    {
+        let address = silent_electrum_server();
+        let err =
+            ElectrumServer::connect_tls("localhost", address.port(), Duration::from_millis(200))
+                .err()
+                .unwrap();
+        assert!(is_timeout(&err), "{:?}", err);
+    }
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn wallet_birthday() {
+    let electrum =
+        ElectrumWalletFile::from_file(&get_test_wallet_file("default_segwit_history")).unwrap();
+    let birthday = electrum.birthday().unwrap();
+    assert_eq!(birthday.height, 2_000_050);
+    assert_eq!(birthday.timestamp, Some(1_624_990_000));
+    assert_eq!(electrum.import_timestamp(), Timestamp::Time(1_624_990_000));
+    let import = electrum
+        .to_core_importdescriptors(None, 100, KeyPolicy::PublicOnly)
+        .unwrap();
+    assert!(import
+        .params
+        .0
+        .iter()
+        .all(|request| request.timestamp == Timestamp::Time(1_624_990_000)));
+
+    // the history survives a roundtrip
+    let parsed = ElectrumWalletFile::from_str(&electrum.to_string()).unwrap();
+    assert_eq!(parsed, electrum);
+    assert_eq!(parsed.history().verified_tx.len(), 2);
+
+    // an earlier confirmed transaction electrum didn't verify yet has no time, so Core has to rescan everything
+    let wallet_file = get_test_wallet_file("default_segwit_history");
+    let mut json: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(wallet_file).unwrap()).unwrap();
+    let address = electrum.addresses().change[1].clone();
+    json["addr_history"][address] = serde_json::json!([["d4".repeat(32), 1_999_999]]);
+    let unverified = ElectrumWalletFile::from_str(&json.to_string()).unwrap();
+    assert_eq!(unverified.birthday().unwrap().height, 1_999_999);
+    assert_eq!(unverified.birthday().unwrap().timestamp, None);
+    assert_eq!(unverified.import_timestamp(), Timestamp::Time(0));
+
+    // an unverified transaction in the same block as a verified one doesn't hide the block's time
+    json["addr_history"][electrum.addresses().change[1].clone()] =
+        serde_json::json!([["d4".repeat(32), 2_000_050]]);
+    let same_block = ElectrumWalletFile::from_str(&json.to_string()).unwrap();
+    assert_eq!(same_block.birthday().unwrap().height, 2_000_050);
+    assert_eq!(
+        same_block.birthday().unwrap().timestamp,
+        Some(1_624_990_000)
+    );
+
+    let fresh = ElectrumWalletFile::from_file(&get_test_wallet_file("default_segwit")).unwrap();
+    assert_eq!(fresh.birthday(), None);
+    assert_eq!(fresh.import_timestamp(), Timestamp::Time(0));
+    assert!(fresh
+        .to_core_importdescriptors(None, 100, KeyPolicy::PublicOnly)
+        .unwrap()
+        .to_json()
+        .unwrap()
+        .contains(r#""timestamp":0"#));
+}
+
+#[test]
+fn cli_from_core() {
+    let secp = bdk_wallet::bitcoin::secp256k1::Secp256k1::new();
+    let master = Xpriv::new_master(Network::Testnet, &[9; 32]).unwrap();
+    let account = master
+        .derive_priv(&secp, &DerivationPath::from_str("84h/1h/0h").unwrap())
+        .unwrap();
+    let listed = |wallet_name: &str| {
+        let listed = |internal: u8| {
+            let desc = format!(
+                "wpkh([{}/84h/1h/0h]{}/{}/*)",
+                master.fingerprint(&secp),
+                account,
+                internal
+            );
+            serde_json::json!({
+                "desc": with_checksum(&desc).unwrap(),
+                "timestamp": 1700000000,
+                "active": true,
+                "internal": internal == 1,
+            })
+        };
+        serde_json::json!({ "wallet_name": wallet_name, "descriptors": [listed(0), listed(1)] })
+            .to_string()
+    };
+    let tempdir = tempfile::tempdir().unwrap();
+    let directory = tempdir.path().to_str().unwrap();
+    let json = tempdir.path().join("hot.json");
+    std::fs::write(&json, listed("hot")).unwrap();
+
+    let (success, stdout, _) = run_cli(&["from-core", json.to_str().unwrap(), directory]);
+    assert!(success);
+    assert!(stdout.contains("wpkh(tpub"));
+    assert!(!stdout.contains("tprv"));
+    let wallet_file = tempdir.path().join("hot_1");
+    let written = ElectrumWalletFile::from_file(&wallet_file).unwrap();
+    assert!(written.keystores()[0].xprv.is_some());
+
+    // an existing wallet file is kept
+    std::fs::write(&wallet_file, "keep").unwrap();
+    let (success, _, stderr) = run_cli(&["from-core", json.to_str().unwrap(), directory]);
+    assert!(!success);
+    assert!(stderr.contains("AlreadyExists"), "{}", stderr);
+    assert_eq!(std::fs::read_to_string(&wallet_file).unwrap(), "keep");
+
+    for name in ["../hot", "sub/hot", "sub\\hot"] {
+        std::fs::write(&json, listed(name)).unwrap();
+        let (success, _, stderr) = run_cli(&["from-core", json.to_str().unwrap(), directory]);
+        assert!(!success);
+        assert!(stderr.contains("InvalidWalletName"), "{}", stderr);
+    }
+    assert!(!tempdir.path().join("sub").exists());
+}
+
+#[test]
+fn cli_taproot_keys() {
+    let wallet_file = get_test_wallet_file("default_segwit");
+    let wallet_file = wallet_file.to_str().unwrap();
+    let (success, stdout, _) = run_cli(&["taproot", wallet_file]);
+    assert!(success);
+    assert!(stdout.contains("tr(tpub") && !stdout.contains("tprv"));
+    let (success, stdout, _) = run_cli(&["taproot", "--keys", "private", wallet_file]);
+    assert!(success);
+    assert!(stdout.contains("tr(tprv"));
+}
+
+#[test]
+fn cli_core_multisig_key() {
+    let cosigner = "Vpub5mUs4UNPA6T3VAmcTWTJ2nCV2oAEFQqBNQQDH62NQNpdAMSyL2Nd3vZXF6uQfNeiCst7asUapZWM9AKmsYCK1BjUrEVhiVm9M4qnbHvDRDe";
+    let (success, _, stderr) = run_cli(&["core", cosigner]);
+    assert!(!success);
+    assert!(stderr.contains(r#"MultisigKindKey("wsh")"#), "{}", stderr);
+}
+
+#[cfg(feature = "electrum_server")]
+#[test]
+fn cli_scan_multisig_key() {
+    let cosigner = "Vpub5mUs4UNPA6T3VAmcTWTJ2nCV2oAEFQqBNQQDH62NQNpdAMSyL2Nd3vZXF6uQfNeiCst7asUapZWM9AKmsYCK1BjUrEVhiVm9M4qnbHvDRDe";
+    let server = silent_electrum_server().to_string();
+    let (success, _, stderr) = run_cli(&["scan", "--timeout", "1", &server, cosigner]);
+    assert!(!success);
+    assert!(stderr.contains(r#"MultisigKindKey("wsh")"#), "{}", stderr);
+}
+
+#[test]
+fn cli_sparrow() {
+    let (success, stdout, _) = run_cli(&[
+        "sparrow",
+        "wpkh([27d81095/84'/1'/0']tpubDEcw4ooTbmw62zBKdkYepoP3z4WWugdeRzPHHAbk8XVsPfBE9AAZMNghiqwtdFgtabaeppBTPmezUkRkQZidLcSJp3XTASbMakHcYauWehZ/0/*)",
+        "single",
+    ]);
+    assert!(success);
+    let wallet: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(
+        wallet["keystores"][0]["keyDerivation"]["derivationPath"],
+        "m/84'/1'/0'"
+    );
+
+    let (success, _, stderr) = run_cli(&["sparrow", "vpub5VXaSncXqxLbdmvrC4Y8z9CszPwuEscADoetWhfrxDFzPUbL5nbVtanYDkrVEutkv9n5A5aCcvRC9swbjDKgHjCZ2tAeae8VsBuPbS8KpXv"]);
+    assert!(!success);
+    assert!(stderr.contains("MissingKeyOrigin(0)"), "{}", stderr);
+}